@@ -0,0 +1,66 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::exit,
+};
+use clap::{Parser, ValueEnum};
+use itertools::Itertools;
+use aitch::usage::format_bytes;
+
+#[derive(Clone, ValueEnum)]
+enum By {
+    Command,
+    /// The name given with hsubmit --job-name.  Jobs without one are grouped together as "-"
+    Name,
+}
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Summarize the resources actually used by finished jobs.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    /// How to group the jobs.
+    #[arg(short, long, value_enum, default_value = "command")]
+    by: By,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let mut file = aitch::lock_state(&mut path);
+    let history = aitch::read_job_history(&mut path);
+    file.unlock().unwrap();
+
+    let groups = history.iter().into_group_map_by(|(job, _usage)| match args.by {
+        By::Command => job.command.clone(),
+        By::Name => if job.name.is_empty() { "-".to_string() } else { job.name.clone() },
+    });
+
+    for key in groups.keys().sorted() {
+        let group = &groups[key];
+        println!("{} {} {:.1} {:.1} {:.1} {} {} {} {}",
+                 group.len(),
+                 group.iter().filter(|(_, u)| !u.succeeded()).count(),
+                 group.iter().map(|(_, u)| u.elapsed).sum::<f64>(),
+                 group.iter().map(|(_, u)| u.user).sum::<f64>(),
+                 group.iter().map(|(_, u)| u.system).sum::<f64>(),
+                 format_bytes(group.iter().map(|(_, u)| u.maxrss).max().unwrap_or(0)),
+                 format_bytes(group.iter().map(|(_, u)| u.read).sum()),
+                 format_bytes(group.iter().map(|(_, u)| u.written).sum()),
+                 key);
+    }
+
+    if groups.is_empty() {
+        println!("no finished jobs found");
+    } else {
+        println!("njobs failed elapsed user system maxrss read written {}",
+                 match args.by { By::Command => "command", By::Name => "name" });
+    }
+    exit(0);
+}
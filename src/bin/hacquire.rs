@@ -0,0 +1,124 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::{Command, Stdio, exit},
+    thread,
+    time::Duration,
+};
+use clap::Parser;
+use aitch::Job;
+
+fn parse_ttl(s: &str) -> Result<Duration, String> {
+    aitch::parse_duration(s).ok_or(format!("unrecognized duration {}", s))
+}
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Take slots for a process which aitch doesn't launch itself.\n\nThe slots are requested like any job's, and once they are free are held under a lease until it is released with hrelease-lease, hkill, or, if --ttl is given, until it expires.  The ID of the lease and the slots given in each queue are printed as shell variable assignments, so that `eval $(hacquire ...)` sets AITCH_LEASE_ID, QUEUE0, QUEUE1, and so on.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    /// A name for the lease, which is shown by hjobs
+    #[arg(short, long)]
+    job_name: Option<String>,
+    /// Release the slots automatically this long (e.g. 4h) after they are given
+    #[arg(short, long, value_parser = parse_ttl)]
+    ttl: Option<Duration>,
+    /// A comma-separated list of numbers denoting the required slots in each queue.
+    #[clap(allow_hyphen_values = true)]
+    nslots: String,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if !aitch::is_nslots(&args.nslots) {
+        eprintln!("{} is not a comma-separated list of numbers", args.nslots);
+        exit(1);
+    }
+    if let Some(Err(e)) = args.job_name.as_deref().map(aitch::check_job_name) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let mut file = aitch::lock_state(&mut path);
+    let nslots_total = aitch::get_nslots_total(&mut path);
+
+    let nslots_required = aitch::expand_nslots(&args.nslots, &nslots_total);
+
+    let jobid = 1+aitch::get_last_jobid(&mut path);
+    let id = jobid.to_string();
+
+    // hschedule gives it its slots as it would hrun, but forgets it should
+    // hacquire be interrupted before then
+    aitch::append_job_to_stack(&mut path, &Job {
+        id: id.clone(),
+        nslots: nslots_required,
+        submit_time: aitch::now(),
+        name: args.job_name.unwrap_or_default(),
+        submit_dir: env::current_dir().map(|d| d.display().to_string()).unwrap_or_default(),
+        runner: std::process::id().to_string(),
+        lease: args.ttl.map_or("forever".to_string(), |t| t.as_secs().to_string()),
+        ..Default::default()
+    });
+
+    aitch::write_last_jobid(&mut path, jobid);
+
+    file.unlock().unwrap();
+
+    Command::new("hschedule").arg(&args.name).spawn().unwrap();
+
+    let mut waiting = false;
+    let queue = loop {
+        file.lock().unwrap();
+        let mut jobs = aitch::read_job_stack(&mut path);
+        match jobs.iter_mut().find(|j| j.id == id) {
+            Some(job) if job.is_running() => {
+                job.runner.clear();
+                let queue = job.queue.clone();
+                aitch::write_job_stack(&mut path, &jobs);
+                file.unlock().unwrap();
+                break queue;
+            }
+            Some(_) => {
+                if !waiting {
+                    eprintln!("lease {} is waiting for its slots", id);
+                    waiting = true;
+                }
+            }
+            None => {
+                eprintln!("lease {} was removed from the queue", id);
+                exit(1);
+            }
+        }
+        file.unlock().unwrap();
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    if let Some(ttl) = args.ttl {
+        let mut cmd = Command::new("hschedule");
+        cmd.args(["--after", &ttl.as_secs().to_string(), &args.name])
+           .stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
+        }
+        // deliberately not waited on:  it has to outlive this process, which
+        // exits right away, after which init reaps it
+        #[allow(clippy::zombie_processes)]
+        cmd.spawn().unwrap();
+    }
+
+    println!("AITCH_LEASE_ID={}", id);
+    for (iqueue, slots) in queue.split(';').enumerate() {
+        println!("QUEUE{}={}", iqueue, slots);
+    }
+
+    exit(0);
+}
@@ -1,24 +1,102 @@
 use std::{
+    collections::HashMap,
     env,
     path::PathBuf,
-    io::BufRead,
     process::exit,
+    str::FromStr,
+    thread,
 };
 use clap::Parser;
+use sysinfo::{Pid, System, RefreshKind, ProcessRefreshKind, MINIMUM_CPU_UPDATE_INTERVAL};
+use serde_json::json;
+use aitch::{Job, format::{self, Format, JOB_COLUMNS}, select::Selectors, usage::{Usage, format_bytes, format_duration}};
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Print the details of all jobs in the queue.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hkill, hnslots, hstart, hstatus, hstop, and hsubmit.")]
+#[command(version, about, long_about = "Print the details of all jobs in the queue.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
 struct Args {
     /// The name of the scheduler, in the case more than one is running.
     #[arg(short, long, default_value = "default")]
     name: String,
-    /// Optionally output just the "running" or "pending" jobs, or a single job ID
-    kind: Option<String>,
+    #[command(flatten)]
+    selectors: Selectors,
+    /// Show the current CPU usage, resident memory, and elapsed time of each running job, summed over all of its processes
+    #[arg(short, long)]
+    live: bool,
+    /// The output format
+    #[arg(long, value_enum, default_value = "text")]
+    format: Format,
+    /// A comma-separated list of the columns to print.  The default for text is id,name,state,nslots,elapsed,submit_time,command, or should any of the jobs have finished id,name,state,status,nslots,elapsed,user,system,maxrss,read,written,command, and for the other formats all of them
+    #[arg(short, long, conflicts_with = "live")]
+    columns: Option<String>,
+    /// A comma-separated list of the columns to sort by, each preceded by a minus sign to reverse the order, e.g. state,-submit_time
+    #[arg(short, long, conflicts_with = "live", allow_hyphen_values = true)]
+    sort: Option<String>,
+    /// Print long values in full in the text table
+    #[arg(long)]
+    no_truncate: bool,
+    /// Instead of a table, show this job and those which depend on it, directly or not, as a tree
+    #[arg(long, value_name = "ROOT", conflicts_with_all = ["live", "columns", "sort", "format"])]
+    dag: Option<String>,
+}
+
+const LIVE_COLUMNS: [&str; 7] = ["id", "pid", "cpu", "rss", "elapsed", "nprocs", "command"];
+
+/// Print the resources currently used by each of `jobs` which is running,
+/// summed over the same processes as hkill would signal.
+fn print_live(jobs: &[&Job], config: &HashMap<String, String>, name: &str, format: Format, width: usize) {
+    let r = RefreshKind::new().with_processes(ProcessRefreshKind::everything());
+    let mut sys = System::new_with_specifics(r);
+    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    sys.refresh_processes();
+
+    let mut rows = Vec::new();
+    let mut text = Vec::new();
+    for job in jobs.iter().filter(|j| j.is_running() && !j.is_lease()) {
+        let pid = Pid::from_str(&job.pid).unwrap();
+        let Some(top) = sys.process(pid) else { continue };
+        let cgroup = aitch::cgroup::path(config, name, &job.id);
+        let pids = aitch::signal::job_processes(&sys, pid, cgroup.as_deref());
+        let processes: Vec<_> = pids.iter().filter_map(|p| sys.process(*p)).collect();
+        let cpu = processes.iter().map(|p| p.cpu_usage()).sum::<f32>();
+        let rss = processes.iter().map(|p| p.memory()).sum();
+        text.push(vec![job.id.clone(), job.pid.clone(), format!("{:.1}", cpu), format_bytes(rss),
+                       format_duration(top.run_time()), processes.len().to_string(),
+                       format::truncate(job.command.clone(), width)]);
+        rows.push(vec![json!(job.id.parse::<u64>().unwrap_or(0)), json!(pid.as_u32()), json!(cpu),
+                       json!(rss), json!(top.run_time()), json!(processes.len()), json!(job.command)]);
+    }
+
+    if format != Format::Text {
+        format::print(format, &LIVE_COLUMNS, &rows);
+    } else if !rows.is_empty() {
+        format::print_table(&LIVE_COLUMNS, &text);
+    } else {
+        println!("no jobs found");
+    }
+}
+
+/// Print `job` indented by `depth`, followed by the jobs which depend on it.
+/// A job depending on several others is shown under each of them.
+fn print_dag(job: &Job, usage: Option<&Usage>, all: &[(&Job, Option<&Usage>)], depth: usize) {
+    let name = if job.name.is_empty() { String::new() } else { format!(" {}", job.name) };
+    println!("{}{}{} {}", "  ".repeat(depth), job.id, name, format::state(job, usage));
+
+    let id = job.id.parse::<u64>().unwrap_or(0);
+    for (child, usage) in all.iter() {
+        // as for hschedule, only dependencies on jobs submitted earlier count
+        let depends = child.id.parse::<u64>().unwrap_or(0) > id
+                      && child.dep.split(' ').any(|d| match d.strip_prefix("name:") {
+                             Some(name) => name == job.name,
+                             None => d == job.id,
+                         });
+        if depends {
+            print_dag(child, *usage, all, depth+1);
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
-    let kind =  args.kind.as_deref();
 
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
@@ -26,77 +104,75 @@ fn main() {
     path.push(&args.name);
 
     let mut file = aitch::lock_state(&mut path);
-    let reader = aitch::job_stack_reader(&mut path);
-
-    let mut id: String = "".to_string();
-    let mut nslots: String = "".to_string();
-    let mut command: String = "".to_string();
-    let mut var: String = "".to_string();
-    let mut out: String = "".to_string();
-    let mut err: String = "".to_string();
-    let mut append: bool = false;
-    let mut dep: String = "".to_string();
-    let mut queue: String = "".to_string();
-    let mut pid;
-    let mut printedsomething: bool = false;
-
-    for (i,line) in reader.lines().enumerate() {
-        match i % 10 {
-            0 => { id = line.unwrap(); },
-            1 => { nslots = line.unwrap(); },
-            2 => { command = line.unwrap(); },
-            3 => { var = line.unwrap(); },
-            4 => { out = line.unwrap(); },
-            5 => { err = line.unwrap(); },
-            6 => { if i>6 { append = line.unwrap().trim().parse().unwrap(); } },
-            7 => { dep = line.unwrap(); },
-            8 => { queue = line.unwrap(); },
-            9 => {
-                if i==9 { continue; }
-                pid = line.unwrap();
-                match kind {
-                    None => {
-                        println!("{} {} {} {} {} {} {} {} {} {}",
-                                 id, nslots, command, var, out, err, append, dep, queue, pid);
-                        printedsomething = true;
-                    }
-                    Some("pending") => {
-                        if pid == "" {
-                            println!("{} {} {} {} {} {} {} {} {} {}",
-                                     id, nslots, command, var, out, err, append, dep, queue, pid);
-                            printedsomething = true;
-                        }
-                    }
-                    Some("running") => {
-                        if pid != "" {
-                            println!("{} {} {} {} {} {} {} {} {} {}",
-                                      id, nslots, command, var, out, err, append, dep, queue, pid);
-                            printedsomething = true;
-                        }
-                    }
-                    Some(_x) => {
-                        if id == kind.unwrap() {
-                            println!("{} {} {} {} {} {} {} {} {} {}",
-                                      id, nslots, command, var, out, err, append, dep, queue, pid);
-                            printedsomething = true;
-                            break;
-                        }
-                    }
-                }
-            }
-            _ => {}
-        }
-    }
+    let config = aitch::get_config(&mut path);
+    let jobs = aitch::read_job_stack(&mut path);
+    let history = aitch::read_job_history(&mut path);
 
     file.unlock().unwrap();
 
-    if printedsomething {
-        println!("id nslots command var out err append dep queue pid");
-    } else if kind == None || kind == Some("pending") || kind == Some("running") {
-        println!("no jobs found");
-    } else {
+    if let Some(root) = &args.dag {
+        let mut all: Vec<(&Job, Option<&Usage>)> = history.iter().map(|(j, u)| (j, Some(u))).collect();
+        all.extend(jobs.iter().map(|j| (j, None)));
+        all.sort_by_key(|(j, _)| j.id.parse::<u64>().unwrap_or(0));
+        let Some((job, usage)) = all.iter().find(|(j, _)| j.id == *root) else {
+            eprintln!("no such job found");
+            exit(1);
+        };
+        print_dag(job, *usage, &all, 0);
+        exit(0);
+    }
+
+    let selected = args.selectors.select(&jobs, &history).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1)
+    });
+
+    let width = if args.no_truncate { usize::MAX } else { format::TRUNCATE_WIDTH };
+
+    if args.live {
+        print_live(&selected.iter().map(|(job, _)| *job).collect::<Vec<_>>(), &config, &args.name, args.format, width);
+        exit(0);
+    }
+
+    if selected.is_empty() && args.selectors.only_ids() {
         eprintln!("no such job found");
         exit(1);
     }
+    if selected.is_empty() && args.format == Format::Text {
+        println!("no jobs found");
+        exit(0);
+    }
+
+    let finished = selected.iter().any(|(_, usage)| usage.is_some());
+    let columns = args.columns.as_deref().unwrap_or(match args.format {
+        Format::Text if finished => format::DEFAULT_FINISHED_JOB_COLUMNS,
+        Format::Text => format::DEFAULT_JOB_COLUMNS,
+        _ => "",
+    });
+    let icolumns = if columns.is_empty() {
+        Ok((0..JOB_COLUMNS.len()).collect())
+    } else {
+        format::parse_columns(columns, &JOB_COLUMNS)
+    };
+    let icolumns: Vec<usize> = icolumns.unwrap_or_else(|e| { eprintln!("{}", e); exit(1) });
+
+    let mut rows: Vec<_> = selected.iter().map(|(job, usage)| format::job_row(job, *usage)).collect();
+    if let Some(sort) = &args.sort {
+        if let Err(e) = format::sort_rows(&mut rows, sort, &JOB_COLUMNS) {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+
+    let names: Vec<&str> = icolumns.iter().map(|i| JOB_COLUMNS[*i]).collect();
+    if args.format == Format::Text {
+        let rows: Vec<Vec<String>> = rows.iter().map(|r| {
+            icolumns.iter().map(|i| format::truncate(format::job_field(JOB_COLUMNS[*i], &r[*i]), width)).collect()
+        }).collect();
+        format::print_table(&names, &rows);
+    } else {
+        let rows: Vec<_> = rows.iter().map(|r| icolumns.iter().map(|i| r[*i].clone()).collect()).collect();
+        format::print(args.format, &names, &rows);
+    }
     exit(0);
 }
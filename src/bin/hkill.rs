@@ -1,25 +1,44 @@
 use std::{
+    collections::HashSet,
     env,
     path::PathBuf,
-    io::BufRead,
     process::{Command, exit},
+    time::Duration,
 };
 use std::str::FromStr;
 use sysinfo::Signal::*;
-use sysinfo::{Pid, System, RefreshKind, ProcessRefreshKind};
+use sysinfo::{Pid, Signal};
 use clap::Parser;
+use aitch::{Job, select::Selectors, signal::name};
+
+fn parse_signal(s: &str) -> Result<Signal, String> {
+    aitch::signal::parse(s).ok_or(format!("unrecognized signal {}", s))
+}
+
+fn parse_grace(s: &str) -> Result<Duration, String> {
+    aitch::parse_duration(s).ok_or(format!("unrecognized duration {}", s))
+}
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Terminate a specific job and remove it from the queue.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hjobs, hnslots, hstart, hstatus, hstop, and hsubmit.")]
+#[command(version, about, long_about = "Terminate jobs and remove them from the queue.\n\nJobs are chosen by ID, range of IDs, or any of the filters hjobs accepts, and are all dealt with at once.  Pending jobs are removed from the queue and leases released, except with a --signal which doesn't ask a job to exit, e.g. USR1, which is only sent to running jobs.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
 struct Args {
     /// The name of the scheduler, in the case more than one is running.
     #[arg(short, long, default_value = "default")]
     name: String,
-    /// The identification number of the job of interest.
-    jobid: String,
+    #[command(flatten)]
+    selectors: Selectors,
+    /// Terminate every pending and running job
+    #[arg(long)]
+    all: bool,
     /// Send SIGKILL (instead of the default SIGTERM)
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "signal")]
     kill: bool,
+    /// The signal to send, e.g. USR1 to ask a job to checkpoint
+    #[arg(short, long, value_parser = parse_signal)]
+    signal: Option<Signal>,
+    /// If the job's processes are still running this long (e.g. 30s) after the signal, send SIGKILL.  Only for signals which ask a job to exit: TERM, INT, HUP, and QUIT
+    #[arg(short, long, value_parser = parse_grace)]
+    grace: Option<Duration>,
     /// Whether to remove a job from aitch's stack if the PID can't be found
     #[arg(short, long)]
     force: bool,
@@ -28,70 +47,115 @@ struct Args {
 fn main() {
     let args = Args::parse();
 
+    if args.all != args.selectors.is_empty() {
+        eprintln!("specify either the jobs to terminate or --all");
+        exit(1);
+    }
+
+    // a job which handles, say, USR1 by checkpointing is meant to keep running
+    let signal = if args.kill { Kill } else { args.signal.unwrap_or(Term) };
+    if args.grace.is_some() && !aitch::signal::terminates(signal) {
+        eprintln!("--grace can't be used with {}, which doesn't ask a job to exit", name(signal));
+        exit(1);
+    }
+
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
     path.push("aitch");
     path.push(&args.name);
 
     let mut file = aitch::lock_state(&mut path);
-    let reader = aitch::job_stack_reader(&mut path);
-
-    let mut id: String = "".to_string();
-    let mut queue: String = "".to_string();
-    let mut foundone: bool = false;
-    let r = RefreshKind::new();
-    let r = r.with_processes(ProcessRefreshKind::everything());
-    let sys = System::new_with_specifics(r);
-
-    for (i,line) in reader.lines().enumerate() {
-        match i % 10 {
-            0 => { id = line.unwrap(); },
-            8 => { queue = line.unwrap(); },
-            9 => {
-                if i==9 { continue; }
-                if id == args.jobid {
-                    foundone = true;
-                    let cloned_line = line.unwrap().clone();
-                    if cloned_line == "" {
-                        aitch::update_slot_availability(&mut path, &queue, false);
-                        aitch::delete_job_from_stack(&mut path, id);
-                    } else {
-                        let pid = Pid::from_str(&cloned_line).unwrap();
-                        match sys.process(pid) {
-                            Some(p) => {
-                                if args.kill {
-                                    if p.kill_with(Kill).is_none() {
-                                        eprintln!("SIGKILL not supported on this platform");
-                                    }
-                                } else {
-                                    if p.kill_with(Term).is_none() {
-                                        eprintln!("SIGTERM not supported on this platform.  use --kill to send SIGKILL instead");
-                                    }
-                                }
-                            }
-                            None => {
-                                if args.force {
-                                    aitch::update_slot_availability(&mut path, &queue, false);
-                                    aitch::delete_job_from_stack(&mut path, id);
-                                } else {
-                                    eprintln!("couldn't find PID.  use --force to delete job from aitch's stack");
-                                }
-                            }
-                        };
+    let jobs = aitch::read_job_stack(&mut path);
+
+    let selected: Vec<Job> = match args.selectors.select(&jobs, &[]) {
+        Ok(selected) => selected.into_iter().map(|(j, _)| j.clone()).collect(),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    let config = aitch::get_config(&mut path);
+    let sys = aitch::signal::processes();
+    let mut signalled: Vec<(&Job, Vec<Pid>)> = Vec::new();
+    let mut removed = HashSet::new();
+
+    for job in selected.iter() {
+        if (job.is_lease() || !job.is_running()) && !aitch::signal::terminates(signal) {
+            // there's nothing to send, say, USR1 to, and it isn't meant to cancel anything
+            continue;
+        } else if job.is_lease() && job.is_running() {
+            aitch::release_lease(&mut path, &config, &args.name, job, "0");
+            removed.insert(job.id.as_str());
+        } else if !job.is_running() {
+            aitch::update_slot_availability(&mut path, &job.queue, false);
+            removed.insert(job.id.as_str());
+        } else {
+            let pid = Pid::from_str(&job.pid).unwrap();
+            let cgroup = aitch::cgroup::path(&config, &args.name, &job.id);
+            if !aitch::signal::job_processes(&sys, pid, cgroup.as_deref()).is_empty() {
+                match aitch::signal::signal_job(&sys, pid, cgroup.as_deref(), signal) {
+                    Some(pids) => { signalled.push((job, pids)); }
+                    None => {
+                        if signal == Kill {
+                            eprintln!("SIGKILL not supported on this platform");
+                        } else {
+                            eprintln!("{} not supported on this platform.  use --kill to send SIGKILL instead", name(signal));
+                        }
+                        break;
                     }
-                    break;
                 }
+            } else if args.force {
+                aitch::update_slot_availability(&mut path, &job.queue, false);
+                removed.insert(job.id.as_str());
+            } else {
+                eprintln!("couldn't find PID of job {}.  use --force to delete it from aitch's stack", job.id);
             }
-            _ => {}
         }
     }
 
+    if !removed.is_empty() {
+        let remaining: Vec<Job> = jobs.into_iter().filter(|j| !removed.contains(j.id.as_str())).collect();
+        aitch::write_job_stack(&mut path, &remaining);
+    }
+
     file.unlock().unwrap();
-    if foundone {
-        Command::new("hschedule").arg(args.name).spawn().unwrap();
-        exit(0);
-    } else {
-        eprintln!("couldn't find job {}", args.jobid);
+
+    if selected.is_empty() {
+        if args.selectors.only_ids() {
+            eprintln!("couldn't find job {}", args.selectors.jobs.join(" "));
+        } else {
+            eprintln!("couldn't find any matching jobs");
+        }
         exit(1);
     }
+
+    let all_pids: Vec<Pid> = signalled.iter().flat_map(|(_, pids)| pids.iter().copied()).collect();
+    if let Some(grace) = args.grace.filter(|_| !all_pids.is_empty() && signal != Kill) {
+        let lingering: Vec<Pid> = aitch::signal::lingering(&all_pids, grace).iter().map(|(p, _)| *p).collect();
+        let sys = aitch::signal::processes();
+        for (job, pids) in signalled.iter() {
+            let lingering_pids: Vec<Pid> = pids.iter().filter(|p| lingering.contains(p)).copied().collect();
+            if lingering_pids.is_empty() {
+                println!("job {} exited after {}", job.id, name(signal));
+            } else {
+                let pid = Pid::from_str(&job.pid).unwrap();
+                let cgroup = aitch::cgroup::path(&config, &args.name, &job.id);
+                aitch::signal::signal_job(&sys, pid, cgroup.as_deref(), Kill);
+                aitch::signal::signal_pids(&sys, &lingering_pids, Kill);
+                println!("job {} was still running {:?} after {}, so sent SIGKILL", job.id, grace, name(signal));
+                let lingering = aitch::signal::lingering(&lingering_pids, Duration::from_secs(2));
+                aitch::signal::report_lingering(&job.id, Kill, &lingering);
+            }
+        }
+    } else if aitch::signal::terminates(signal) {
+        let lingering = aitch::signal::lingering(&all_pids, Duration::from_secs(2));
+        for (job, pids) in signalled.iter() {
+            let lingering: Vec<_> = lingering.iter().filter(|(p, _)| pids.contains(p)).cloned().collect();
+            aitch::signal::report_lingering(&job.id, signal, &lingering);
+        }
+    }
+
+    Command::new("hschedule").arg(args.name).spawn().unwrap();
+    exit(0);
 }
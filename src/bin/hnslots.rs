@@ -4,15 +4,20 @@ use std::{
     process::exit,
 };
 use clap::Parser;
+use serde_json::json;
+use aitch::format::Format;
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Print the number of slots.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hjobs, hkill, hstart, hstatus, hstop, and hsubmit.")]
+#[command(version, about, long_about = "Print the number of slots.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
 struct Args {
     /// The name of the scheduler, in the case more than one is running.
     #[arg(short, long, default_value = "default")]
     name: String,
     /// Optionally output just the number of "used" or "free" slots
     kind: Option<String>,
+    /// The output format.  All but text print the total, used, and free slots in each queue
+    #[arg(long, value_enum, default_value = "text")]
+    format: Format,
 }
 
 fn main() {
@@ -27,6 +32,15 @@ fn main() {
     let nslots_total = aitch::get_nslots_total(&mut path);
     let nslots_free = aitch::get_nslots_free(&mut path);
 
+    if args.format != Format::Text {
+        let rows: Vec<_> = nslots_total.iter().zip(nslots_free.iter()).enumerate()
+                                       .map(|(i, (t, f))| vec![json!(i), json!(t), json!(t-f), json!(f)])
+                                       .collect();
+        aitch::format::print(args.format, &["queue", "total", "used", "free"], &rows);
+        file.unlock().unwrap();
+        exit(0);
+    }
+
     match args.kind.as_deref() {
         None => println!("{}",
                           nslots_total.iter()
@@ -0,0 +1,113 @@
+use std::{
+    env,
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    process::exit,
+    thread,
+    time::Duration,
+};
+use clap::Parser;
+use aitch::Job;
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Print the standard output of a job.\n\nWith --follow, the output is printed as it is written, waiting for the job to start if it is still pending, until the job finishes.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    /// The job's identification number
+    id: String,
+    /// Print the standard error instead.  Both are in the same file if hsubmit was given --log
+    #[arg(short, long)]
+    err: bool,
+    /// Keep printing the output as it is written, until the job finishes
+    #[arg(short, long)]
+    follow: bool,
+}
+
+enum Status {
+    Pending,
+    Running,
+    Finished,
+}
+
+/// Job `id`, and whether it is pending, running, or finished.
+fn find(path: &mut PathBuf, id: &str) -> Option<(Job, Status)> {
+    let mut file = aitch::lock_state(path);
+    let jobs = aitch::read_job_stack(path);
+    let history = aitch::read_job_history(path);
+    file.unlock().unwrap();
+
+    match jobs.into_iter().find(|j| j.id == id) {
+        Some(job) if job.is_running() => Some((job, Status::Running)),
+        Some(job) => Some((job, Status::Pending)),
+        None => history.into_iter().find(|(j, _)| j.id == id).map(|(j, _)| (j, Status::Finished)),
+    }
+}
+
+/// Print whatever has been written to `log` since `offset`, and return
+/// where it now ends.
+fn print_from(log: &str, offset: u64) -> u64 {
+    let Ok(mut file) = fs::File::open(log) else { return offset };
+    let len = file.metadata().map_or(0, |m| m.len());
+    // started over, as log files are truncated when the job starts
+    let offset = if len < offset { 0 } else { offset };
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer).unwrap();
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&buffer).unwrap();
+    stdout.flush().unwrap();
+    offset + buffer.len() as u64
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let Some((job, mut status)) = find(&mut path, &args.id) else {
+        eprintln!("couldn't find job {}", args.id);
+        exit(1);
+    };
+    let log = if !job.log.is_empty() { job.log } else if args.err { job.err } else { job.out };
+
+    if !args.follow {
+        if matches!(status, Status::Pending) {
+            eprintln!("job {} hasn't started yet", args.id);
+            exit(1);
+        }
+        match fs::read(&log) {
+            Ok(contents) => io::stdout().write_all(&contents).unwrap(),
+            Err(error) => {
+                eprintln!("couldn't read {}: {}", log, error);
+                exit(1);
+            }
+        }
+        exit(0);
+    }
+
+    let mut offset = 0;
+    loop {
+        match status {
+            Status::Pending => {}
+            Status::Running => { offset = print_from(&log, offset); }
+            Status::Finished => {
+                print_from(&log, offset);
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(500));
+        status = match find(&mut path, &args.id) {
+            Some((_, status)) => status,
+            // removed from the queue by hkill before it started
+            None => break,
+        };
+    }
+
+    exit(0);
+}
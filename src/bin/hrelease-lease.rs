@@ -0,0 +1,63 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::{Command, exit},
+};
+use clap::Parser;
+use aitch::Job;
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Release the slots held by leases taken with hacquire.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrun, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    /// The IDs of the leases, as printed by hacquire in AITCH_LEASE_ID
+    #[arg(required = true)]
+    leases: Vec<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let mut file = aitch::lock_state(&mut path);
+    let jobs = aitch::read_job_stack(&mut path);
+    let config = aitch::get_config(&mut path);
+
+    let mut failed = false;
+    let mut released = Vec::new();
+    for id in args.leases.iter() {
+        match jobs.iter().find(|j| j.id == *id) {
+            None => {
+                eprintln!("couldn't find lease {}", id);
+                failed = true;
+            }
+            Some(job) if !job.is_lease() => {
+                eprintln!("job {} is not a lease.  use hkill to terminate it", id);
+                failed = true;
+            }
+            Some(job) => {
+                if job.is_running() {
+                    aitch::release_lease(&mut path, &config, &args.name, job, "0");
+                }
+                released.push(job.id.as_str());
+            }
+        }
+    }
+
+    if !released.is_empty() {
+        let remaining: Vec<Job> = jobs.iter().filter(|j| !released.contains(&j.id.as_str())).cloned().collect();
+        aitch::write_job_stack(&mut path, &remaining);
+    }
+
+    file.unlock().unwrap();
+
+    Command::new("hschedule").arg(&args.name).spawn().unwrap();
+
+    exit(if failed { 1 } else { 0 });
+}
@@ -0,0 +1,155 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::{Command, exit},
+    thread,
+    time::{Duration, Instant},
+};
+use clap::Parser;
+use aitch::{Job, usage::Usage};
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Run a command interactively once the slots it requires are free.\n\nThe command is queued like any other job, but is run by hrun itself, attached to the terminal, and holds its slots until it exits.  Ctrl-C goes to the command, not to hrun.  The exit status is that of the command.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hstart, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    /// VARIABLE=VALUE.  Set VARIABLE equal to VALUE when running command.  This option can be used multiple times.
+    #[arg(short, long)]
+    #[arg(value_parser = aitch::parse_var)]
+    var: Option<Vec<(String, String)>>,
+    /// A name for the job, which is shown by hjobs and passed to it as AITCH_JOB_NAME
+    #[arg(short, long)]
+    job_name: Option<String>,
+    /// A comma-separated list of numbers denoting the required slots in each queue.
+    #[clap(allow_hyphen_values = true)]
+    nslots: String,
+    /// The command to execute
+    #[arg(required = true)]
+    command: Vec<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if !aitch::is_nslots(&args.nslots) {
+        eprintln!("{} is not a comma-separated list of numbers", args.nslots);
+        exit(1);
+    }
+    if let Some(Err(e)) = args.job_name.as_deref().map(aitch::check_job_name) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let mut file = aitch::lock_state(&mut path);
+    let nslots_total = aitch::get_nslots_total(&mut path);
+
+    let nslots_required = aitch::expand_nslots(&args.nslots, &nslots_total);
+
+    let jobid = 1+aitch::get_last_jobid(&mut path);
+    let id = jobid.to_string();
+
+    aitch::append_job_to_stack(&mut path, &Job {
+        id: id.clone(),
+        nslots: nslots_required,
+        command: shell_words::join(&args.command),
+        argv: args.command.clone(),
+        var: args.var.unwrap_or_default(),
+        submit_time: aitch::now(),
+        name: args.job_name.unwrap_or_default(),
+        submit_dir: env::current_dir().map(|d| d.display().to_string()).unwrap_or_default(),
+        runner: std::process::id().to_string(),
+        ..Default::default()
+    });
+
+    aitch::write_last_jobid(&mut path, jobid);
+
+    file.unlock().unwrap();
+
+    Command::new("hschedule").arg(&args.name).spawn().unwrap();
+
+    // wait for hschedule to give it its slots
+    let mut waiting = false;
+    let mut job = loop {
+        file.lock().unwrap();
+        let jobs = aitch::read_job_stack(&mut path);
+        file.unlock().unwrap();
+        match jobs.into_iter().find(|j| j.id == id) {
+            Some(job) if job.is_running() => break job,
+            Some(_) => {
+                if !waiting {
+                    eprintln!("job {} is waiting for its slots", id);
+                    waiting = true;
+                }
+            }
+            None => {
+                eprintln!("job {} was removed from the queue", id);
+                exit(1);
+            }
+        }
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    let config = aitch::get_config(&mut path);
+    let nslots: Vec<usize> = job.nslots.split(',').map(|x| x.parse::<usize>().unwrap()).collect();
+    let cgroup = aitch::cgroup::create(&config, &args.name, &id, &nslots, &job.queue);
+    let mut cmd = aitch::job_command(&job, &args.name);
+    if let Some(dir) = &cgroup {
+        aitch::cgroup::enter(&mut cmd, dir);
+    }
+
+    let start = Instant::now();
+    let usage = match cmd.spawn() {
+        Ok(mut proc) => {
+            // record the command's PID, so that hkill and hjobs --live find it
+            job.pid = proc.id().to_string();
+            file.lock().unwrap();
+            let mut jobs = aitch::read_job_stack(&mut path);
+            if let Some(j) = jobs.iter_mut().find(|j| j.id == id) {
+                j.pid = job.pid.clone();
+            }
+            aitch::write_job_stack(&mut path, &jobs);
+            file.unlock().unwrap();
+
+            // the command shares the terminal's process group, so it receives Ctrl-C itself
+            #[cfg(unix)]
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_IGN);
+                libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+            }
+
+            let mut usage = Usage::wait(&mut proc, start).unwrap();
+            if let Some(dir) = &cgroup {
+                usage.add_cgroup(dir);
+            }
+            usage
+        }
+        Err(error) => {
+            eprintln!("error launching job {}: {}", id, error);
+            Usage { status: "error".to_string(), ..Default::default() }
+        }
+    };
+
+    file.lock().unwrap();
+    if let Some(dir) = &cgroup {
+        aitch::cgroup::remove(dir);
+    }
+    // hstop --force deleted the state folder while the command was running
+    if !path.is_dir() {
+        exit(usage.exit_code());
+    }
+    aitch::append_job_to_history(&mut path, &job, &usage);
+    aitch::notify_finished(&config, &args.name, &job, &usage);
+    aitch::update_slot_availability(&mut path, &job.queue, false);
+    aitch::delete_job_from_stack(&mut path, &id);
+    file.unlock().unwrap();
+
+    Command::new("hschedule").arg(&args.name).spawn().unwrap();
+
+    exit(usage.exit_code());
+}
@@ -1,188 +1,260 @@
 use std::{
     env,
     fs,
-    collections::{HashMap, HashSet},
-    path::PathBuf,
-    io::{BufRead, BufWriter, Write},
+    io,
+    collections::HashSet,
+    path::{Path, PathBuf},
     process::{Command, exit, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
 use clap::Parser;
+use std::str::FromStr;
+use sysinfo::Pid;
+use aitch::{Job, usage::Usage};
+
+/// Open a log file, creating its directory if need be, and truncating it
+/// unless `append`.
+fn open_log(path: &str, append: bool) -> io::Result<fs::File> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::File::options().create(true).write(true).append(append).truncate(!append).open(path)
+}
+
+/// Redirect the standard streams of `cmd` as `job` requests.  Returns the
+/// log to capture its output in, if it is to be.
+fn redirect(cmd: &mut Command, job: &Job) -> io::Result<Option<fs::File>> {
+    // never the terminal, should hschedule have been launched from one
+    if job.input.is_empty() {
+        cmd.stdin(Stdio::null());
+    } else {
+        cmd.stdin(fs::File::open(&job.input)?);
+    }
+    if !job.log.is_empty() {
+        let log = open_log(&job.log, job.append)?;
+        cmd.stdout(Stdio::piped())
+           .stderr(Stdio::piped());
+        return Ok(Some(log));
+    }
+    if !job.out.is_empty() && job.out == job.err {
+        let outputs = open_log(&job.out, job.append)?;
+        let errors = outputs.try_clone()?;
+        cmd.stdout(Stdio::from(outputs))
+           .stderr(Stdio::from(errors));
+    } else {
+        if !job.out.is_empty() {
+            cmd.stdout(open_log(&job.out, job.append)?);
+        }
+        if !job.err.is_empty() {
+            cmd.stderr(open_log(&job.err, job.append)?);
+        }
+    }
+    Ok(None)
+}
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     name: String,
+    /// Seconds to wait before scheduling, so as to release a lease when it expires
+    #[arg(long, hide = true)]
+    after: Option<u64>,
 }
 
 fn main() {
     let args = Args::parse();
 
+    if let Some(after) = args.after {
+        thread::sleep(Duration::from_secs(after));
+    }
+
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
     path.push("aitch");
     path.push(&args.name);
 
     let mut file = aitch::lock_state(&mut path);
+    let config = aitch::get_config(&mut path);
+    let mut jobs = aitch::read_job_stack(&mut path);
+
+    // forget jobs whose hrun has gone, say because its terminal was closed,
+    // unless what it launched is still running
+    if jobs.iter().any(|j| !j.runner.is_empty()) {
+        let sys = aitch::signal::processes();
+        let alive = |pid: &str| Pid::from_str(pid).is_ok_and(|p| aitch::signal::is_alive(&sys, p));
+        let (gone, kept): (Vec<Job>, Vec<Job>) = jobs.into_iter().partition(|j| {
+            !j.runner.is_empty() && !alive(&j.runner) && (j.pid == j.runner || !alive(&j.pid))
+        });
+        for job in gone.iter().filter(|j| j.is_running()) {
+            aitch::update_slot_availability(&mut path, &job.queue, false);
+            aitch::append_job_to_history(&mut path, job, &Usage { status: "unknown".to_string(), ..Default::default() });
+        }
+        if !gone.is_empty() {
+            aitch::write_job_stack(&mut path, &kept);
+        }
+        jobs = kept;
+    }
+
+    // release leases which have run out
+    if jobs.iter().any(|j| j.is_running() && j.is_expired()) {
+        let (expired, kept): (Vec<Job>, Vec<Job>) = jobs.into_iter().partition(|j| j.is_running() && j.is_expired());
+        for job in expired.iter() {
+            aitch::release_lease(&mut path, &config, &args.name, job, "expired");
+        }
+        aitch::write_job_stack(&mut path, &kept);
+        jobs = kept;
+    }
+
     let nslots_free = aitch::get_nslots_free(&mut path);
     let slot_availability = aitch::get_slot_availability(&mut path);
-    let reader = aitch::job_stack_reader(&mut path);
-
-    let mut id: String = "".to_string();
-    let mut nslots: String = "".to_string();
-    let mut command: String = "".to_string();
-    let mut var: String = "".to_string();
-    let mut out: String = "".to_string();
-    let mut err: String = "".to_string();
-    let mut append: bool = false;
-    let mut dep = HashSet::new();
-    let mut pid;
-    let mut lines = Vec::new();
-    let mut foundone: bool = false;
-    let mut nslots_required = Vec::new();
 
     // scan stack for a job which fits in the free slots
     let mut prior_jobs = HashSet::new();
-    for (i,line) in reader.lines().enumerate() {
-        lines.push(line.as_ref().unwrap().clone());
-        if !foundone {
-            match i % 10 {
-                0 => { id = line.unwrap(); },
-                1 => { nslots = line.unwrap(); },
-                2 => { command = line.unwrap(); },
-                3 => { var = line.unwrap(); },
-                4 => { out = line.unwrap(); },
-                5 => { err = line.unwrap(); },
-                6 => { if i>6 { append = line.unwrap().trim().parse().unwrap(); } },
-                7 => { dep = line.unwrap().split(" ").map(|x| x.to_string()).collect(); },
-                8 => {},  // queue
-                9 => {
-                    if i==9 { continue; }
-                    prior_jobs.insert(id.clone());
-                    if prior_jobs.intersection(&dep).collect::<Vec<&String>>().len() > 0 {
-                        continue;
-                    }
-                    pid = line.unwrap();
-                    if pid=="" {
-                        nslots_required = nslots.clone()
-                                                .split(",")
-                                                .map(|x| x.parse::<usize>().unwrap()).collect();
-                        foundone = nslots_free.clone().into_iter()
-                                              .zip(nslots_required.clone())
-                                              .map(|(x, y)| if x>=y {true} else {false})
-                                              .all(|x| x);
-                    }
-                }
-                _ => {}
+    let mut prior_names = HashSet::new();
+    let mut ijob = None;
+    let mut iskip = None;
+    let mut nslots_required = Vec::new();
+    for (i, job) in jobs.iter().enumerate() {
+        prior_jobs.insert(job.id.as_str());
+        let waiting = job.dep.split(' ').any(|d| match d.strip_prefix("name:") {
+            Some(name) => prior_names.contains(name),
+            None => prior_jobs.contains(d),
+        });
+        if !job.name.is_empty() {
+            prior_names.insert(job.name.as_str());
+        }
+        if waiting {
+            continue;
+        }
+        if !job.is_running() && job.is_up_to_date() {
+            iskip = Some(i);
+            break;
+        }
+        if !job.is_running() {
+            nslots_required = job.nslots.split(',')
+                                        .map(|x| x.parse::<usize>().unwrap()).collect();
+            if nslots_free.iter().zip(&nslots_required).all(|(x, y)| x >= y) {
+                ijob = Some(i);
+                break;
             }
         }
     }
 
+    // skip a job whose outputs are already newer than its inputs, as if it had succeeded
+    if let Some(iskip) = iskip {
+        let usage = Usage { status: "skipped".to_string(), ..Default::default() };
+        aitch::append_job_to_history(&mut path, &jobs[iskip], &usage);
+        aitch::notify_finished(&config, &args.name, &jobs[iskip], &usage);
+        aitch::delete_job_from_stack(&mut path, &jobs[iskip].id);
+        Command::new("hschedule").arg(&args.name).spawn().unwrap();
+        file.unlock().unwrap();
+        exit(0);
+    }
+
     // launch such a job if found
-    if foundone {
-        // construct command
-        let mut args2: Vec<_> = shell_words::split(&command).unwrap();
-        let exe = args2.remove(0);
-        let mut cmd = Command::new(exe);
-
-        let mut env_vars = HashMap::new();
-        let mut queue = String::new();
-
-        // set QUEUE environment variables
-        #[allow(non_snake_case)]
-        let QUEUE = String::from("QUEUE");
-        for (iqueue,_n) in nslots_required.iter().enumerate() {
-            let mut q = String::new();
-            q += &QUEUE;
-            q += &iqueue.to_string();
-            let mut iter = slot_availability[iqueue].iter();
-            let mut slots = Vec::<usize>::new();
-            let mut n = _n.clone();
-            while n>0 {
-                let mut i = iter.position(|x| !x).unwrap();
-                if slots.len() > 0 {
-                    i = i + slots.last().unwrap() + 1;
-                }
-                slots.push(i);
-                n -= 1
+    if let Some(ijob) = ijob {
+        let id = jobs[ijob].id.clone();
+
+        let queue = aitch::allocate_slots(&slot_availability, &nslots_required);
+
+        // hrun runs the job itself, attached to the terminal, once it has its slots
+        if !jobs[ijob].runner.is_empty() {
+            aitch::update_slot_availability(&mut path, &queue, true);
+            jobs[ijob].queue = queue.clone();
+            jobs[ijob].pid = jobs[ijob].runner.clone();
+            jobs[ijob].start_time = aitch::now();
+            aitch::write_job_stack(&mut path, &jobs);
+            if let Some(command) = config.get("on_job_start") {
+                aitch::notify(command, &args.name, &jobs[ijob], None);
             }
-            let slots_str = slots.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",");
-            queue += &slots_str;
-            queue += ";";
-            env_vars.insert(q, slots_str);
+            Command::new("hschedule").arg(&args.name).spawn().unwrap();
+            file.unlock().unwrap();
+            exit(0);
         }
-        queue.pop();
-
-        // set user-supplied environment variables
-        if var != "" {
-            let varvals: Vec<_> = var.split(" ").collect();
-            for varval in varvals {
-                let thisvarval: Vec<_> = varval.split("=").collect();
-                env_vars.insert(thisvarval[0].to_string(), thisvarval[1].to_string());
-            }
+
+        // construct command
+        let mut cmd = aitch::job_command(&Job { queue: queue.clone(), ..jobs[ijob].clone() }, &args.name);
+
+        // make it the leader of a new process group, so that hkill can signal all of its processes
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
         }
 
-        cmd.args(args2).envs(&env_vars);
+        // confine to a cgroup
+        let cgroup = aitch::cgroup::create(&config, &args.name, &id, &nslots_required, &queue);
+        if let Some(dir) = &cgroup {
+            aitch::cgroup::enter(&mut cmd, dir);
+        }
 
         // redirection
-        if out != "" && out == err {
-            let outputs = fs::File::options().create(true).write(true).append(append).open(out).unwrap();
-            let errors = outputs.try_clone().unwrap();
-            cmd.stdout(Stdio::from(outputs))
-               .stderr(Stdio::from(errors));
-        } else {
-            if out != "" {
-                let outputs = fs::File::options().create(true).write(true).append(append).open(out).unwrap();
-                cmd.stdout(outputs);
-            }
-            if err != "" {
-                let errors = fs::File::options().create(true).write(true).append(append).open(err).unwrap();
-                cmd.stderr(errors);
-            }
-        }
-     
+        let redirected = redirect(&mut cmd, &jobs[ijob]);
+
         // spawn job
-        match cmd.spawn() {
-            Ok(mut proc) => {
+        let start = Instant::now();
+        match redirected.and_then(|log| Ok((cmd.spawn()?, log))) {
+            Ok((mut proc, log)) => {
+                let capture = log.map(|log| {
+                    let max_size = jobs[ijob].log_max_size.parse::<u64>().ok();
+                    aitch::capture::start(&mut proc, PathBuf::from(&jobs[ijob].log), log, max_size)
+                });
+
                 // update nslots_free
                 aitch::update_slot_availability(&mut path, &queue, true);
 
                 // update job_stack with queue and PID
-                path.push("job_stack");
-                let job_stack = fs::File::create(&path).unwrap();
-                path.pop();
-                let mut writer = BufWriter::new(job_stack);
-                let mut lines_iter = lines.iter();
-                let mut line;
-                let mut iline: i32 = 0;
-                loop {
-                    line = lines_iter.next();
-                    if line.is_none() { break; }
-                    if iline % 10 == 0 && *line.unwrap() == id {
-                        writeln!(writer, "{}", line.unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", lines_iter.next().unwrap()).unwrap();
-                        writeln!(writer, "{}", queue).unwrap();
-                        writeln!(writer, "{}", proc.id().to_string()).unwrap();
-                        lines_iter.next();
-                        lines_iter.next();
-                        iline += 10;
-                    } else {
-                        writeln!(writer, "{}", line.unwrap()).unwrap();
-                        iline += 1;
-                    }
+                jobs[ijob].queue = queue.clone();
+                jobs[ijob].pid = proc.id().to_string();
+                jobs[ijob].start_time = aitch::now();
+                aitch::write_job_stack(&mut path, &jobs);
+
+                if let Some(command) = config.get("on_job_start") {
+                    aitch::notify(command, &args.name, &jobs[ijob], None);
                 }
-                writer.flush().unwrap();
+
+                // launch any other jobs which also fit, such as the rest of those submitted together
+                let mut launcher = Command::new("hschedule").arg(&args.name).spawn().unwrap();
+
+                // terminate it should it run out of time
+                let time_limit = jobs[ijob].time_limit.parse::<u64>().ok().map(|limit| {
+                    aitch::signal::time_limit(Pid::from_u32(proc.id()), cgroup.clone(), Duration::from_secs(limit))
+                });
 
                 // wait for job to finish
                 file.unlock().unwrap();
-                proc.wait().unwrap();
+                let mut usage = Usage::wait(&mut proc, start).unwrap();
+
+                // reap it, unless it is still waiting for a job of its own
+                launcher.try_wait().ok();
+                let timed_out = time_limit.is_some_and(|t| t.stop());
+                if let Some(capture) = capture {
+                    if !aitch::capture::finish(capture, Duration::from_secs(1)) {
+                        eprintln!("warning: job {} left processes behind which still hold {} open.  their output won't be logged",
+                                  id, jobs[ijob].log);
+                    }
+                }
+                if let Some(dir) = &cgroup {
+                    usage.add_cgroup(dir);
+                }
+                if timed_out {
+                    usage.status = "timeout".to_string();
+                }
                 file.lock().unwrap();
 
+                if let Some(dir) = &cgroup {
+                    aitch::cgroup::remove(dir);
+                }
+
+                // hstop --force deleted the state folder while the job was running
+                if !path.is_dir() {
+                    exit(0);
+                }
+
+                aitch::append_job_to_history(&mut path, &jobs[ijob], &usage);
+                aitch::notify_finished(&config, &args.name, &jobs[ijob], &usage);
+
                 // update nslots_free
                 aitch::update_slot_availability(&mut path, &queue, false);
 
@@ -191,11 +263,17 @@ fn main() {
             }
             Err(error) => {
                 eprintln!("error launching job {}: {}", id, error);
+                if let Some(dir) = &cgroup {
+                    aitch::cgroup::remove(dir);
+                }
+                let usage = Usage { status: "error".to_string(), ..Default::default() };
+                aitch::append_job_to_history(&mut path, &jobs[ijob], &usage);
+                aitch::notify_finished(&config, &args.name, &jobs[ijob], &usage);
             }
         }
 
         // delete job from stack
-        aitch::delete_job_from_stack(&mut path, id);
+        aitch::delete_job_from_stack(&mut path, &id);
 
         // run scheduler
         Command::new("hschedule").arg(&args.name).spawn().unwrap();
@@ -9,13 +9,34 @@ use clap::Parser;
 use sysinfo::{System, CpuRefreshKind};
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Provision a new scheduler.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hjobs, hkill, hnslots, hstatus, hstop, and hsubmit.")]
+#[command(version, about, long_about = "Provision a new scheduler.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstatus, hstop, hsubmit, hsubmit-dag, and hwait.")]
 struct Args {
     /// An optional name to give the scheduler, in the case more than one is needed.
     #[arg(short, long, default_value = "default")]
     name: String,
     /// A comma-separated list of numbers denoting the total slots in each queue.  Default is one queue with as many slots as CPU cores.
     nslots: Option<String>,
+    /// Path to a delegated cgroup v2 subtree.  If given, each job is run in its own child cgroup whose limits are derived from the slots it requested.
+    #[arg(long)]
+    cgroup: Option<String>,
+    /// With --cgroup, the index of the queue whose slots are CPU cores.  Used to set cpuset.cpus and cpu.max.
+    #[arg(long, requires = "cgroup")]
+    cgroup_cpus: Option<usize>,
+    /// QUEUE:SIZE.  With --cgroup, the index of the queue whose slots are units of memory and the size of each slot (e.g. 2:1G).  Used to set memory.max.
+    #[arg(long, requires = "cgroup")]
+    cgroup_memory: Option<String>,
+    /// Directory in which to save the standard output and error of jobs not given --out or --err, which is otherwise the state folder, and so deleted by hstop.
+    #[arg(long)]
+    log_dir: Option<String>,
+    /// A command to run with the shell whenever a job starts.  %j is replaced with the job's ID, %n with the scheduler's name, and %x with the job's name, which are also in AITCH_JOB_ID, AITCH_SCHEDULER_NAME, and AITCH_JOB_NAME
+    #[arg(long, value_name = "COMMAND")]
+    on_job_start: Option<String>,
+    /// A command to run with the shell whenever a job finishes, with the same replacements as --on-job-start, plus %s with the job's exit status and %e with the seconds it took, also in AITCH_JOB_STATUS and AITCH_JOB_ELAPSED
+    #[arg(long, value_name = "COMMAND")]
+    on_job_end: Option<String>,
+    /// A command to run with the shell whenever a job fails, in addition to --on-job-end, with the same replacements
+    #[arg(long, value_name = "COMMAND")]
+    on_job_fail: Option<String>,
 }
 
 fn main() {
@@ -30,6 +51,22 @@ fn main() {
         None => { &ncpus }
     };
     let nslots_vec = nslots.split(",").map(|x| x.parse::<usize>().unwrap());
+    let nqueues = nslots_vec.clone().count();
+
+    if args.cgroup_cpus.is_some_and(|x| x >= nqueues) {
+        eprintln!("--cgroup-cpus must be less than the number of queues");
+        exit(1);
+    }
+    let cgroup_memory = args.cgroup_memory.as_deref().map(|x| {
+        match x.split_once(':')
+               .and_then(|(q, b)| Some((q.parse::<usize>().ok()?, aitch::cgroup::parse_bytes(b)?))) {
+            Some((iqueue, bytes)) if iqueue < nqueues => (iqueue, bytes),
+            _ => {
+                eprintln!("--cgroup-memory must be QUEUE:SIZE, with QUEUE less than the number of queues");
+                exit(1);
+            }
+        }
+    });
 
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
@@ -47,6 +84,49 @@ fn main() {
         exit(1)
     }
 
+    let mut config = Vec::new();
+    if let Some(log_dir) = &args.log_dir {
+        match fs::create_dir_all(log_dir).and_then(|_| std::path::absolute(log_dir)) {
+            Ok(log_dir) => config.push(format!("log_dir={}", log_dir.display())),
+            Err(error) => {
+                eprintln!("couldn't create log directory {}: {}", log_dir, error);
+                exit(1);
+            }
+        }
+    }
+    for (key, command) in [("on_job_start", &args.on_job_start), ("on_job_end", &args.on_job_end),
+                           ("on_job_fail", &args.on_job_fail)] {
+        if let Some(command) = command {
+            if command.contains('\n') {
+                eprintln!("--{} must be a single line", key.replace('_', "-"));
+                exit(1);
+            }
+            config.push(format!("{}={}", key, command));
+        }
+    }
+    if let Some(cgroup) = &args.cgroup {
+        // hschedule runs in whichever directory the job which launched it was submitted from
+        let cgroup = std::path::absolute(cgroup).unwrap_or(PathBuf::from(cgroup));
+        match aitch::cgroup::delegate(&cgroup) {
+            Ok(controllers) => {
+                config.push(format!("cgroup={}", cgroup.display()));
+                config.push(format!("cgroup_instance={}", aitch::now()));
+                config.push(format!("cgroup_controllers={}", controllers.join(",")));
+                if let Some(iqueue) = args.cgroup_cpus {
+                    config.push(format!("cgroup_cpus={}", iqueue));
+                }
+                if let Some((iqueue, bytes)) = cgroup_memory {
+                    config.push(format!("cgroup_memory_queue={}", iqueue));
+                    config.push(format!("cgroup_memory_bytes={}", bytes));
+                }
+            }
+            Err(error) => {
+                eprintln!("warning: couldn't enable controllers in cgroup {}: {}.  jobs will run without resource enforcement",
+                          cgroup.display(), error);
+            }
+        }
+    }
+
     std::fs::create_dir_all(&path).unwrap();
 
     path.push("slot_availability");
@@ -58,12 +138,12 @@ fn main() {
     writer.flush().ok();
     path.pop();
 
-    path.push("job_stack");
-    fs::write(&path, "id\nnslots\ncommand\nvar\nout\nerr\nappend\ndep\nqueue\npid\n").unwrap();
-    path.pop();
+    aitch::write_job_stack(&mut path, &[]);
+
+    aitch::write_last_jobid(&mut path, 0);
 
-    path.push("last_jobid");
-    fs::write(&path, "0").unwrap();
+    path.push("config");
+    fs::write(&path, config.iter().map(|x| x.to_string() + "\n").collect::<String>()).unwrap();
     path.pop();
 
     println!("started {} scheduler with nslots = {}", args.name, nslots);
@@ -1,29 +1,38 @@
 use std::{
     fs,
-    io::{Error, BufRead},
+    io::Error,
     env,
     path::PathBuf,
     process::exit,
 };
 use clap::Parser;
-use aitch::eprintln_help;
+use serde_json::json;
+use aitch::{eprintln_help, format::Format};
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Print the number of slots and number of jobs.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hjobs, hkill, hnslots, hstart, hstop, and hsubmit.")]
+#[command(version, about, long_about = "Print the number of slots and number of jobs.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstop, hsubmit, hsubmit-dag, and hwait.")]
 
 struct Args {
     /// The name of the scheduler, in the case more than one is running.  Default is all.
     #[arg(short, long)]
     name: Option<Vec<String>>,
+    /// The output format
+    #[arg(long, value_enum, default_value = "text")]
+    format: Format,
 }
 
+const COLUMNS: [&str; 7] = ["name", "nslots_total", "nslots_free", "nslots_used",
+                            "njobs_total", "njobs_running", "njobs_pending"];
+
 fn main() {
     let args = Args::parse();
 
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
     path.push("aitch");
-    println!("path to state folder: {}", path.to_str().unwrap());
+    if args.format == Format::Text {
+        println!("path to state folder: {}", path.to_str().unwrap());
+    }
 
     let schedulers = match args.name {
         Some(name) => Vec::from(name),
@@ -39,49 +48,42 @@ fn main() {
         }
     };
 
+    let mut rows = Vec::new();
     for scheduler in schedulers.iter() {
         path.push(scheduler);
 
         let mut file = aitch::lock_state(&mut path);
         let nslots_total = aitch::get_nslots_total(&mut path);
         let nslots_free = aitch::get_nslots_free(&mut path);
-        let reader = aitch::job_stack_reader(&mut path);
+        let jobs = aitch::read_job_stack(&mut path);
         path.pop();
 
         let nslots_used: Vec<usize> = nslots_total.clone().into_iter()
                                                 .zip(nslots_free.clone())
                                                 .map(|(x, y)| x-y).collect();
 
-        let mut pid;
-        let mut total = 0;
-        let mut pending = 0;
-        let mut running = 0;
-        for (i,line) in reader.lines().enumerate() {
-            match i % 10 {
-                9 => {
-                    if i==9 { continue; }
-                    pid = line.unwrap();
-                    total += 1;
-                    if pid == "" {
-                        pending += 1; }
-                    if pid != "" {
-                        running += 1; }
-                }
-                _ => {}
-            }
-        }
+        let total = jobs.len();
+        let running = jobs.iter().filter(|j| j.is_running()).count();
+        let pending = total - running;
 
-        println!("{}  {} {} {}  {} {} {}",
-                 PathBuf::from(scheduler).file_name().unwrap().to_str().unwrap(),
-                 nslots_total.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","),
-                 nslots_free.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","),
-                 nslots_used.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","),
-                 total, running, pending);
+        let name = PathBuf::from(scheduler).file_name().unwrap().to_str().unwrap().to_string();
+        if args.format == Format::Text {
+            println!("{}  {} {} {}  {} {} {}",
+                     name,
+                     nslots_total.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","),
+                     nslots_free.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","),
+                     nslots_used.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","),
+                     total, running, pending);
+        }
+        rows.push(vec![json!(name), json!(nslots_total), json!(nslots_free), json!(nslots_used),
+                       json!(total), json!(running), json!(pending)]);
 
         file.unlock().unwrap();
     }
 
-    if schedulers.len()>0 {
+    if args.format != Format::Text {
+        aitch::format::print(args.format, &COLUMNS, &rows);
+    } else if !schedulers.is_empty() {
         println!("name nslots:total,free,used njobs:total,running,pending");
     }
 
@@ -3,16 +3,16 @@ use std::{
     fs,
     io::Error,
     path::PathBuf,
-    io::BufRead,
     process::exit,
+    time::Duration,
 };
 use std::str::FromStr;
 use sysinfo::Signal::*;
-use sysinfo::{Pid, System};
+use sysinfo::Pid;
 use clap::{Parser, ArgGroup};
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Tear down a scheduler.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hjobs, hkill, hnslots, hstart, hstatus, and hsubmit.")]
+#[command(version, about, long_about = "Tear down a scheduler.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hsubmit, hsubmit-dag, and hwait.")]
 #[command(group(ArgGroup::new("vers") .args(["name", "all"])))]
 struct Args {
     /// The name of the scheduler, in the case more than one is running.
@@ -45,36 +45,29 @@ fn main() {
         path.push(scheduler);
 
         let mut file = aitch::lock_state(&mut path);
-        let reader = aitch::job_stack_reader(&mut path);
+        let config = aitch::get_config(&mut path);
+        let jobs = aitch::read_job_stack(&mut path);
+        let name = PathBuf::from(scheduler).file_name().unwrap().to_str().unwrap().to_string();
 
-        let sys = System::new_all();
+        let sys = aitch::signal::processes();
 
-        for (i,line) in reader.lines().enumerate() {
-            match i % 10 {
-                9 => {
-                    if i==9 { continue; }
-                    let cloned_line = line.unwrap().clone();
-                    if !args.force {
-                        eprintln!("jobs are still queued.  use --force to stop anyway");
-                        exit(1);
-                    }
-                    if cloned_line == "" { continue; }
-                    let pid = Pid::from_str(&cloned_line).unwrap();
-                    match sys.process(pid) {
-                        Some(p) => {
-                            if let Some(_res) = p.kill_with(Kill)
-                            {
-                                //println!("kill: {res}");
-                            } else {
-                                eprintln!("kill: signal not supported on this platform");
-                            }
-                        }
-                        None => { }
-                    };
-                }
-                _ => {}
+        if !jobs.is_empty() && !args.force {
+            eprintln!("jobs are still queued.  use --force to stop anyway");
+            exit(1);
+        }
+        let mut signalled = Vec::new();
+        for job in jobs.iter().filter(|j| j.is_running() && !j.is_lease()) {
+            let pid = Pid::from_str(&job.pid).unwrap();
+            let cgroup = aitch::cgroup::path(&config, &name, &job.id);
+            match aitch::signal::signal_job(&sys, pid, cgroup.as_deref(), Kill) {
+                Some(pids) => signalled.push((job.id.clone(), pids)),
+                None => eprintln!("kill: signal not supported on this platform"),
             }
         }
+        for (id, pids) in signalled.iter() {
+            let lingering = aitch::signal::lingering(pids, Duration::from_secs(2));
+            aitch::signal::report_lingering(id, Kill, &lingering);
+        }
 
         fs::remove_dir_all(&path).unwrap();
         path.pop();
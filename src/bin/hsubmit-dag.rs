@@ -0,0 +1,214 @@
+use std::{
+    env,
+    fs,
+    io,
+    path::PathBuf,
+    process::{Command, exit},
+};
+use clap::Parser;
+use toml::{Table, Value};
+use aitch::{Job, absolute};
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Submit a pipeline of jobs which depend on one another.\n\nThe pipeline is described by a TOML file with a table for each step, e.g.\n\n    [steps.align]\n    nslots = \"1,0,4\"\n    command = \"align sample7 > sample7.bam\"\n\n    [steps.summarize]\n    nslots = [1, 0, 1]\n    command = [\"summarize\", \"sample7.bam\"]\n    needs = [\"align\"]\n\nA command given as a string is run by the shell, and one given as an array is run as is.  Each step can also specify out, err, var, a table of environment variables, and inputs and outputs, arrays of files, just as for hsubmit.  The steps are all submitted at once, each named after its step and depending on the jobs of the steps it needs, and their names and job IDs are printed.  Use hjobs --dag to see their progress.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, and hwait.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    /// The TOML file describing the pipeline, or - to read it from standard input
+    file: String,
+}
+
+struct Step {
+    name: String,
+    nslots: String,
+    command: String,
+    argv: Vec<String>,
+    needs: Vec<String>,
+    out: Option<String>,
+    err: Option<String>,
+    var: Vec<(String, String)>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+fn string(value: &Value, key: &str) -> Result<String, String> {
+    value.as_str().map(String::from).ok_or(format!("{} must be a string", key))
+}
+
+fn strings(value: &Value, key: &str) -> Result<Vec<String>, String> {
+    value.as_array().and_then(|a| a.iter().map(|v| v.as_str().map(String::from)).collect())
+         .ok_or(format!("{} must be an array of strings", key))
+}
+
+fn parse_step(name: &str, table: &Value) -> Result<Step, String> {
+    let Some(table) = table.as_table() else {
+        return Err("must be a table".to_string());
+    };
+    aitch::check_job_name(name).map_err(|_| "step names must be non-empty and cannot contain spaces")?;
+
+    let mut step = Step {
+        name: name.to_string(),
+        nslots: String::new(),
+        command: String::new(),
+        argv: Vec::new(),
+        needs: Vec::new(),
+        out: None,
+        err: None,
+        var: Vec::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+    };
+    for (key, value) in table.iter() {
+        match key.as_str() {
+            "nslots" => {
+                step.nslots = match value.as_array() {
+                    Some(array) => array.iter().map(|x| x.as_integer().map(|x| x.to_string()))
+                                        .collect::<Option<Vec<_>>>()
+                                        .ok_or("nslots must be an array of integers")?
+                                        .join(","),
+                    None => string(value, key)?,
+                };
+            }
+            "command" => {
+                (step.command, step.argv) = match value.as_array() {
+                    Some(_) => {
+                        let argv = strings(value, key)?;
+                        (shell_words::join(&argv), argv)
+                    }
+                    None => {
+                        let script = string(value, key)?;
+                        (script.clone(), aitch::shell_argv(&script))
+                    }
+                };
+            }
+            "needs" => { step.needs = strings(value, key)?; }
+            "out" => { step.out = Some(string(value, key)?); }
+            "err" => { step.err = Some(string(value, key)?); }
+            "var" => {
+                step.var = value.as_table().ok_or("var must be a table")?.iter()
+                                .map(|(k, v)| string(v, &format!("var.{}", k)).map(|v| (k.clone(), v)))
+                                .collect::<Result<_, _>>()?;
+            }
+            "inputs" => { step.inputs = strings(value, key)?; }
+            "outputs" => { step.outputs = strings(value, key)?; }
+            _ => { return Err(format!("unknown key {}", key)); }
+        }
+    }
+
+    if step.nslots.is_empty() {
+        return Err("nslots must be given".to_string());
+    }
+    if !aitch::is_nslots(&step.nslots) {
+        return Err(format!("{} is not a comma-separated list of numbers", step.nslots));
+    }
+    if step.argv.is_empty() || step.command.is_empty() {
+        return Err("no command given".to_string());
+    }
+    Ok(step)
+}
+
+/// The steps described in `file`, ordered such that each comes after those
+/// it needs but otherwise as they are listed.
+fn read_steps(file: &str) -> Result<Vec<Step>, String> {
+    let contents = if file == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    }.map_err(|e| format!("couldn't read {}: {}", file, e))?;
+    let table = contents.parse::<Table>().map_err(|e| format!("couldn't parse {}: {}", file, e.message()))?;
+
+    if let Some(key) = table.keys().find(|k| *k != "steps") {
+        return Err(format!("unknown key {} in {}", key, file));
+    }
+    let Some(steps) = table.get("steps").and_then(|s| s.as_table()).filter(|s| !s.is_empty()) else {
+        return Err(format!("no steps found in {}", file));
+    };
+
+    let mut steps = steps.iter()
+                         .map(|(name, step)| parse_step(name, step).map_err(|e| format!("step {}: {}", name, e)))
+                         .collect::<Result<Vec<_>, _>>()?;
+    for step in steps.iter() {
+        if let Some(need) = step.needs.iter().find(|n| !steps.iter().any(|s| s.name == **n)) {
+            return Err(format!("step {}: needs unknown step {}", step.name, need));
+        }
+    }
+
+    let mut ordered: Vec<Step> = Vec::new();
+    while !steps.is_empty() {
+        let Some(i) = steps.iter().position(|s| s.needs.iter().all(|n| ordered.iter().any(|o| o.name == *n))) else {
+            let names: Vec<_> = steps.iter().map(|s| s.name.as_str()).collect();
+            return Err(format!("steps {} need one another in a cycle", names.join(", ")));
+        };
+        ordered.push(steps.remove(i));
+    }
+    Ok(ordered)
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let steps = read_steps(&args.file).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let mut file = aitch::lock_state(&mut path);
+    let nslots_total = aitch::get_nslots_total(&mut path);
+    let log_dir = aitch::get_config(&mut path).get("log_dir").map(PathBuf::from).unwrap_or(path.clone());
+
+    let mut id = aitch::get_last_jobid(&mut path);
+
+    let submit_dir = env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+    let mut ids: Vec<(String, String)> = Vec::new();
+    for step in steps {
+        id += 1;
+        let id = id.to_string();
+
+        let nslots_required = aitch::expand_nslots(&step.nslots, &nslots_total);
+
+        let log_path = |pattern: Option<&str>, extension: &str| {
+            aitch::log_path(pattern, &log_dir, extension, &id, &args.name, &step.name)
+        };
+
+        let dep = step.needs.iter()
+                            .map(|n| ids.iter().find(|(name, _)| name == n).unwrap().1.clone())
+                            .collect::<Vec<_>>()
+                            .join(" ");
+
+        aitch::append_job_to_stack(&mut path, &Job {
+            id: id.clone(),
+            nslots: nslots_required,
+            out: log_path(step.out.as_deref(), ".out"),
+            err: log_path(step.err.as_deref(), ".err"),
+            command: step.command,
+            argv: step.argv,
+            var: step.var,
+            dep,
+            submit_time: aitch::now(),
+            name: step.name.clone(),
+            submit_dir: submit_dir.clone(),
+            inputs: step.inputs.into_iter().map(absolute).collect(),
+            outputs: step.outputs.into_iter().map(absolute).collect(),
+            ..Default::default()
+        });
+        ids.push((step.name, id));
+    }
+
+    aitch::write_last_jobid(&mut path, id);
+
+    file.unlock().unwrap();
+
+    for (name, id) in ids.iter() {
+        println!("{} {}", name, id);
+    }
+
+    Command::new("hschedule").arg(&args.name).spawn().unwrap();
+
+    exit(0);
+}
@@ -1,43 +1,304 @@
 use std::{
     env,
     fs,
-    path::PathBuf,
-    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    io,
     process::{Command, exit},
+    time::Duration,
 };
 use clap::Parser;
+use serde_json::{Map, Value};
+use aitch::{Job, absolute, is_nslots, parse_var};
+
+fn parse_size(s: &str) -> Result<usize, String> {
+    aitch::cgroup::parse_bytes(s).ok_or(format!("unrecognized size {}", s))
+}
+
+fn parse_time(s: &str) -> Result<Duration, String> {
+    aitch::parse_duration(s).ok_or(format!("unrecognized duration {}", s))
+}
+
+/// The arguments in the #AITCH lines of the comment block at the top of
+/// `script`, e.g. "#AITCH --nslots 2,1,8".
+fn directives(script: &str) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(script) else { return Vec::new() };
+    let mut directives = Vec::new();
+    for line in contents.lines().map(|l| l.trim()) {
+        if let Some(rest) = line.strip_prefix("#AITCH") {
+            match shell_words::split(rest) {
+                Ok(words) => directives.extend(words),
+                Err(_) => {
+                    eprintln!("couldn't parse directive in {}: {}", script, line);
+                    exit(1);
+                }
+            }
+        } else if !line.is_empty() && !line.starts_with('#') {
+            break;
+        }
+    }
+    directives
+}
 
 #[derive(Parser)]
-#[command(version, about, long_about = "Add a new job to the queue.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hjobs, hkill, hnslots, hstart, hstatus, and hstop.")]
+#[command(args_override_self = true)]
+#[command(version, about, long_about = "Add a new job to the queue.\n\nShould the command be a script, any arguments on lines beginning with #AITCH in its opening comments are used as if they were given before those on the command line, which therefore take precedence.\n\nMany jobs can be submitted at once with --from-file, each line of which holds the arguments for one job, quoted as for sh.  Blank lines and those beginning with # are skipped.  The IDs of the jobs are printed one per line.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit-dag, and hwait.")]
 struct Args {
     /// The name of the scheduler, in the case more than one is running.
     #[arg(short, long, default_value = "default")]
     name: String,
     /// VARIABLE=VALUE.  Set VARIABLE equal to VALUE when running command.  This option can be used multiple times.
     #[arg(short, long)]
-    var: Option<Vec<String>>,
-    /// Path to file in which to save the standard output
+    #[arg(value_parser = parse_var)]
+    var: Option<Vec<(String, String)>>,
+    /// Path to file in which to save the standard output.  %j is replaced with the job's ID, %n with the scheduler's name, and %x with the job's name, or that of its program if it has none.  Missing directories are created
     #[arg(short, long)]
     out: Option<String>,
-    /// Path to file in which to save the standard error
+    /// Path to file in which to save the standard error, with the same replacements as --out
     #[arg(short, long)]
     err: Option<String>,
+    /// Path to file in which to save the standard output and error together, each line prefixed with the time it was written and "out" or "err", with the same replacements as --out
+    #[arg(short, long, conflicts_with_all = ["out", "err"])]
+    log: Option<String>,
+    /// Once the --log file grows larger than this, e.g. 100M, rename it with a .1 suffix, replacing any such earlier file, and start a new one
+    #[arg(long, value_name = "SIZE", requires = "log", value_parser = parse_size)]
+    log_max_size: Option<usize>,
+    /// Path to file from which to read the standard input, which is otherwise empty
+    #[arg(short, long = "in", value_name = "IN")]
+    input: Option<String>,
     /// Do not overwrite log files
     #[arg(short, long)]
     append: bool,
-    /// The identification number of a job that must finish first
+    /// The identification number of a job that must finish first, or name:NAME to wait for every earlier job with that name
     #[arg(short, long)]
     dep: Option<Vec<String>>,
+    /// A name for the job, which is shown by hjobs and passed to it as AITCH_JOB_NAME
+    #[arg(short, long)]
+    job_name: Option<String>,
+    /// Run the job in a copy of this environment: ALL of it, NONE of it, or just the comma-separated VARIABLEs listed.  Otherwise it inherits the environment of whichever aitch command happens to launch it
+    #[arg(long, conflicts_with = "export_env")]
+    export: Option<String>,
+    /// The same as --export ALL
+    #[arg(long)]
+    export_env: bool,
+    /// KEY=VALUE.  Label the job, so that hjobs and hkill can select it by tag.  This option can be used multiple times.
+    #[arg(short, long)]
+    tag: Option<Vec<String>>,
+    /// A comma-separated list of the files the job reads.  This option can be used multiple times
+    #[arg(long, value_name = "FILES", value_delimiter = ',')]
+    inputs: Option<Vec<String>>,
+    /// A comma-separated list of the files the job writes.  If all of them exist and are newer than every file in --inputs when the job would start, it is skipped instead, and jobs which depend on it run as if it had succeeded.  This option can be used multiple times
+    #[arg(long, value_name = "FILES", value_delimiter = ',')]
+    outputs: Option<Vec<String>>,
+    /// The longest the job may run for (e.g. 1h), after which all of its processes are sent SIGTERM, and SIGKILL 30 seconds later should any remain
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    time: Option<Duration>,
     /// A comma-separated list of numbers denoting the required slots in each queue.
     #[clap(allow_hyphen_values = true)]
-    nslots: String,
+    nslots: Option<String>,
+    /// The same as NSLOTS, for use in a script's #AITCH directives
+    #[arg(long = "nslots", value_name = "NSLOTS")]
+    nslots_option: Option<String>,
+    /// A command to run with the shell once the job has finished, e.g. 'notify-send "job %j: %s"'.  %j is replaced with the job's ID, %n with the scheduler's name, %x with the job's name, %s with its exit status, and %e with the seconds it took
+    #[arg(long, value_name = "COMMAND")]
+    notify_cmd: Option<String>,
+    /// Run the command with `sh -c`, so that it can contain pipes, redirections, and the like.  Otherwise each argument is passed to the program as is
+    #[arg(long)]
+    shell: bool,
     /// The command to execute
     command: Vec<String>,
+    /// Wait for the job to finish, and exit with its status, as hwait does
+    #[arg(short, long)]
+    wait: bool,
+    /// Submit the jobs listed in this file, or standard input if "-", one per line as NSLOTS, options, and command.  Any other options given are the defaults for each
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["nslots", "command"])]
+    from_file: Option<String>,
+}
+
+impl Args {
+    /// NSLOTS is optional if --nslots is given, in which case the first
+    /// positional argument might be the command instead.
+    fn normalize(mut self) -> Args {
+        if let Some(nslots) = self.nslots.take_if(|n| !is_nslots(n)) {
+            self.command.insert(0, nslots);
+        }
+        self
+    }
+
+    /// Parse `tokens`, preceded by `defaults` and, should the command be a
+    /// script, its directives, both of which the tokens take precedence over.
+    fn parse_with(defaults: &[String], tokens: &[String]) -> Result<Args, String> {
+        let program: Vec<String> = env::args().take(1).collect();
+        let parse = |words: Vec<String>| {
+            Args::try_parse_from(program.iter().cloned().chain(words)).map(Args::normalize).map_err(|e| e.to_string())
+        };
+        let mut args = parse([defaults, tokens].concat())?;
+
+        if let Some(script) = args.command.first().filter(|c| Path::new(c).is_file()).cloned() {
+            let directives = directives(&script);
+            if !directives.is_empty() {
+                let embedded = parse(directives.clone())?;
+                if embedded.nslots.is_some() || !embedded.command.is_empty() {
+                    return Err(format!("unrecognized directive in {}: {}", script, directives.join(" ")));
+                }
+                args = parse([defaults, &directives, tokens].concat())?;
+                args.command[0] = absolute(script);
+            }
+        }
+        Ok(args)
+    }
+
+    /// The slots required, once the arguments have been checked.
+    fn validate(&self) -> Result<String, String> {
+        let Some(nslots) = self.nslots.clone().or(self.nslots_option.clone()) else {
+            return Err("the number of slots required must be given, either as the first argument or with --nslots".to_string());
+        };
+        if !is_nslots(&nslots) {
+            return Err(format!("{} is not a comma-separated list of numbers", nslots));
+        }
+        if self.command.is_empty() {
+            return Err("no command given".to_string());
+        }
+        if let Some(name) = &self.job_name {
+            aitch::check_job_name(name)?;
+        }
+        if self.tag.iter().flatten().any(|t| !t.contains('=') || t.contains(char::is_whitespace)) {
+            return Err("tags must be of the form KEY=VALUE and cannot contain spaces".to_string());
+        }
+        Ok(nslots)
+    }
+
+    /// The job these arguments describe, given its `id`, the directory for
+    /// log files by default, and the total number of slots in each queue.
+    fn job(self, nslots: &str, id: &str, log_dir: &Path, nslots_total: &[usize]) -> Job {
+        let nslots_required = aitch::expand_nslots(nslots, nslots_total);
+
+        let var = self.var.unwrap_or_default();
+
+        let job_name = self.job_name.clone().unwrap_or_else(|| {
+            self.command.first().and_then(|c| Path::new(c).file_name()).map(|c| c.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+        let log_path = |pattern: Option<&str>, extension: &str| {
+            aitch::log_path(pattern, log_dir, extension, id, &self.name, &job_name)
+        };
+        let (out, err) = match self.log {
+            Some(_) => (String::new(), String::new()),
+            None => (log_path(self.out.as_deref(), ".out"), log_path(self.err.as_deref(), ".err")),
+        };
+        let captured = self.log.as_deref().map(|pattern| log_path(Some(pattern), ""));
+
+        let dep = match self.dep {
+            Some(content) => { content.join(" ") },
+            None => "".to_string(),
+        };
+
+        let (command, argv) = if self.shell {
+            let script = self.command.join(" ");
+            (script.clone(), aitch::shell_argv(&script))
+        } else {
+            (shell_words::join(&self.command), self.command)
+        };
+
+        let export = if self.export_env { Some("ALL") } else { self.export.as_deref() };
+        let env = match export {
+            None => "".to_string(),
+            Some(export) => {
+                let vars: Map<String, Value> = env::vars_os()
+                    .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+                    .filter(|(k, _)| match export {
+                        "ALL" => true,
+                        "NONE" => false,
+                        _ => export.split(',').any(|x| x == k),
+                    })
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect();
+                Value::Object(vars).to_string()
+            }
+        };
+
+        Job {
+            id: id.to_string(),
+            nslots: nslots_required,
+            command,
+            argv,
+            input: self.input.map(absolute).unwrap_or_default(),
+            log: captured.unwrap_or_default(),
+            log_max_size: self.log_max_size.map(|x| x.to_string()).unwrap_or_default(),
+            notify_cmd: self.notify_cmd.unwrap_or_default(),
+            var,
+            out,
+            err,
+            append: self.append,
+            dep,
+            submit_time: aitch::now(),
+            name: self.job_name.unwrap_or_default(),
+            tags: self.tag.unwrap_or_default().join(" "),
+            submit_dir: env::current_dir().map(|d| d.display().to_string()).unwrap_or_default(),
+            env,
+            inputs: self.inputs.unwrap_or_default().into_iter().map(absolute).collect(),
+            outputs: self.outputs.unwrap_or_default().into_iter().map(absolute).collect(),
+            time_limit: self.time.map(|t| t.as_secs().to_string()).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The command line without --from-file, whose other flags are the defaults
+/// for each job in the file.
+fn defaults() -> Vec<String> {
+    let mut defaults = Vec::new();
+    let mut tokens = env::args().skip(1);
+    while let Some(token) = tokens.next() {
+        if token == "--from-file" {
+            tokens.next();
+        } else if !token.starts_with("--from-file=") {
+            defaults.push(token);
+        }
+    }
+    defaults
+}
+
+/// The arguments for each job listed in `file`, one per line.
+fn read_jobs(file: &str) -> Result<Vec<(Args, String)>, String> {
+    let contents = if file == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    }.map_err(|e| format!("couldn't read {}: {}", file, e))?;
+
+    let defaults = defaults();
+    let mut jobs = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words = shell_words::split(line).map_err(|e| e.to_string());
+        let args = words.and_then(|w| Args::parse_with(&defaults, &w))
+                        .and_then(|a| a.validate().map(|n| (a, n)))
+                        .map_err(|e| format!("line {} of {}: {}", i+1, file, e.trim_end()))?;
+        jobs.push(args);
+    }
+    Ok(jobs)
 }
 
 fn main() {
     let args = Args::parse();
 
+    let jobs = match &args.from_file {
+        Some(file) => read_jobs(file),
+        None => Args::parse_with(&[], &env::args().skip(1).collect::<Vec<_>>())
+                     .and_then(|a| a.validate().map(|n| vec![(a, n)])),
+    };
+    let jobs = jobs.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    if let Some((job, _)) = jobs.iter().find(|(j, _)| j.name != args.name) {
+        eprintln!("every job must be submitted to scheduler {}, not {}", args.name, job.name);
+        exit(1);
+    }
+
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
     path.push("aitch");
@@ -45,76 +306,32 @@ fn main() {
 
     let mut file = aitch::lock_state(&mut path);
     let nslots_total = aitch::get_nslots_total(&mut path);
+    let log_dir = aitch::get_config(&mut path).get("log_dir").map(PathBuf::from).unwrap_or(path.clone());
 
-    let nslots_required = args.nslots.split(',')
-                                     .map(|x| x.parse::<i32>().unwrap())
-                                     .zip(nslots_total.into_iter())
-                                     .map(|(x,y)| if x>=0 {x} else {y.try_into().unwrap()})
-                                     .map(|x| x.to_string())
-                                     .collect::<Vec<String>>()
-                                     .join(",");
-
-    let var = match args.var {
-        Some(content) => { content.join(" ") },
-        None => { "".to_string() }
-    };
+    let mut id = aitch::get_last_jobid(&mut path);
 
-    path.push("last_jobid");
-    let fid = fs::File::open(&path).unwrap();
-    let mut buffer = BufReader::new(fid);
-    let mut first_line = String::new();
-    buffer.read_line(&mut first_line).unwrap();
-    let id = (1+first_line.parse::<usize>().unwrap()).to_string();
-    path.pop();
-
-    let out = match args.out {
-        Some(content) => { content },
-        None => {
-            path.push(id.clone()+".out");
-            let tmp = path.clone().into_os_string().into_string().unwrap();
-            path.pop();
-            tmp
-        }
-    };
+    let mut ids = Vec::new();
+    for (job, nslots) in jobs {
+        id += 1;
+        let job = job.job(&nslots, &id.to_string(), &log_dir, &nslots_total);
+        aitch::append_job_to_stack(&mut path, &job);
+        ids.push(job.id);
+    }
 
-    let err = match args.err {
-        Some(content) => { content },
-        None => {
-            path.push(id.clone()+".err");
-            let tmp = path.clone().into_os_string().into_string().unwrap();
-            path.pop();
-            tmp
-        }
-    };
-
-    let dep = match args.dep {
-        Some(content) => { content.join(" ") },
-        None => "".to_string(),
-    };
-
-    path.push("job_stack");
-    let mut f = fs::OpenOptions::new().append(true).open(&path).unwrap();
-    writeln!(f, "{}", id).unwrap();
-    writeln!(f, "{}", nslots_required).unwrap();
-    writeln!(f, "{}", args.command.join(" ")).unwrap();
-    writeln!(f, "{}", var).unwrap();
-    writeln!(f, "{}", out).unwrap();
-    writeln!(f, "{}", err).unwrap();
-    writeln!(f, "{}", args.append).unwrap();
-    writeln!(f, "{}", dep).unwrap();
-    writeln!(f, "").unwrap();
-    writeln!(f, "").unwrap();
-    path.pop();
-
-    path.push("last_jobid");
-    fs::write(&path, &id).unwrap();
-    path.pop();
+    aitch::write_last_jobid(&mut path, id);
 
     file.unlock().unwrap();
 
-    println!("{}", id);
+    for id in ids.iter() {
+        println!("{}", id);
+    }
+
+    Command::new("hschedule").arg(&args.name).spawn().unwrap();
 
-    Command::new("hschedule").arg(args.name).spawn().unwrap();
+    if args.wait {
+        let status = Command::new("hwait").args(["--name", &args.name]).args(&ids).status().unwrap();
+        exit(status.code().unwrap_or(1));
+    }
 
     exit(0);
 }
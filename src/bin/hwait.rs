@@ -0,0 +1,94 @@
+use std::{
+    env,
+    path::PathBuf,
+    process::exit,
+    thread,
+    time::Duration,
+};
+use clap::Parser;
+use aitch::select::Selectors;
+
+#[derive(Parser)]
+#[command(version, about, long_about = "Wait for jobs to finish.\n\nJobs are chosen by ID, range of IDs, or any of the filters hjobs accepts.  The exit status is that of the job if only one was chosen, and otherwise 0 if every job completed successfully and 1 if any failed or was removed from the queue before it ran.  A lease which expired counts as having completed successfully.\n\nA detailed tutorial and the source code is at https://github.com/JaneliaSciComp/aitch\n\nSee also hacct, hacquire, hjobs, hkill, hnslots, houtput, hrelease-lease, hrun, hstart, hstatus, hstop, hsubmit, and hsubmit-dag.")]
+struct Args {
+    /// The name of the scheduler, in the case more than one is running.
+    #[arg(short, long, default_value = "default")]
+    name: String,
+    #[command(flatten)]
+    selectors: Selectors,
+    /// Wait for every pending and running job, though not for leases, which might never be released
+    #[arg(long)]
+    all: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if args.all != args.selectors.is_empty() {
+        eprintln!("specify either the jobs to wait for or --all");
+        exit(1);
+    }
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push(&args.name);
+
+    let mut file = aitch::lock_state(&mut path);
+    let jobs = aitch::read_job_stack(&mut path);
+    let history = aitch::read_job_history(&mut path);
+    file.unlock().unwrap();
+
+    let ids: Vec<String> = match args.selectors.select(&jobs, &history) {
+        Ok(selected) => selected.into_iter().filter(|(j, _)| !(args.all && j.is_lease()))
+                                .map(|(j, _)| j.id.clone()).collect(),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    if ids.is_empty() {
+        if args.selectors.only_ids() {
+            eprintln!("couldn't find job {}", args.selectors.jobs.join(" "));
+        } else {
+            eprintln!("couldn't find any matching jobs");
+        }
+        exit(1);
+    }
+
+    loop {
+        file.lock().unwrap();
+        let jobs = aitch::read_job_stack(&mut path);
+        file.unlock().unwrap();
+        if !jobs.iter().any(|j| ids.contains(&j.id)) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    file.lock().unwrap();
+    let history = aitch::read_job_history(&mut path);
+    file.unlock().unwrap();
+
+    let mut codes = Vec::new();
+    for id in ids.iter() {
+        match history.iter().find(|(j, _)| j.id == *id) {
+            Some((_, usage)) => {
+                if !usage.succeeded() {
+                    eprintln!("job {} failed with status {}", id, usage.status);
+                }
+                codes.push(usage.exit_code());
+            }
+            None => {
+                eprintln!("job {} was removed from the queue before it ran", id);
+                codes.push(1);
+            }
+        }
+    }
+
+    exit(match codes[..] {
+        [code] => code,
+        _ => if codes.iter().all(|c| *c == 0) { 0 } else { 1 },
+    });
+}
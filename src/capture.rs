@@ -0,0 +1,111 @@
+//! Capturing a job's standard output and error into a single log, each line
+//! prefixed with when it was written and which stream it came from, e.g.
+//! "2024-05-01T12:00:00.123Z err warning: ...".
+//!
+//! Should the log grow beyond its maximum size it is renamed with a ".1"
+//! suffix, replacing any earlier such file, and a new one started, so that a
+//! job never takes up more than about twice that on disk.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::Child,
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Longer lines are split into several records.
+const MAX_LINE: u64 = 64 << 10;
+
+struct Log {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+    max_size: Option<u64>,
+}
+
+impl Log {
+    fn write(&mut self, record: &[u8]) -> io::Result<()> {
+        let len = record.len() as u64;
+        if self.max_size.is_some_and(|max| self.size > 0 && self.size + len > max) {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.path, &rotated)?;
+            self.file = fs::File::create(&self.path)?;
+            self.size = 0;
+        }
+        self.file.write_all(record)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// Send each line read from `stream` to `sender`, prefixed with the time and `tag`.
+fn forward(stream: impl Read + Send + 'static, tag: &'static str, sender: Sender<Vec<u8>>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut line = Vec::new();
+            match (&mut reader).take(MAX_LINE).read_until(b'\n', &mut line) {
+                Ok(n) if n > 0 => {}
+                _ => break,
+            }
+            if !line.ends_with(b"\n") {
+                line.push(b'\n');
+            }
+            let mut record = format!("{} {} ", humantime::format_rfc3339_millis(SystemTime::now()), tag).into_bytes();
+            record.append(&mut line);
+            if sender.send(record).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Copy the piped standard output and error of `child` to `file`, which is
+/// at `path` and is rotated once it exceeds `max_size` bytes.  The thread
+/// returned finishes once both streams have been closed.
+pub fn start(child: &mut Child, path: PathBuf, file: fs::File, max_size: Option<u64>) -> JoinHandle<()> {
+    let size = file.metadata().map_or(0, |m| m.len());
+    let mut log = Log { path, file, size, max_size };
+
+    let (sender, receiver) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        forward(stdout, "out", sender.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward(stderr, "err", sender);
+    }
+
+    thread::spawn(move || {
+        // keep reading even if writing fails, lest the job block on a full pipe
+        let mut failed = false;
+        for record in receiver {
+            if failed {
+                continue;
+            }
+            if let Err(error) = log.write(&record) {
+                eprintln!("warning: couldn't write to log {}: {}", log.path.display(), error);
+                failed = true;
+            }
+        }
+    })
+}
+
+/// Wait up to `timeout` for the thread returned by `start` to log the last
+/// of the job's output, which it never will should the job have left a
+/// background process holding its standard output or error open.  Returns
+/// whether it did.
+pub fn finish(capture: JoinHandle<()>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !capture.is_finished() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    capture.join().unwrap();
+    true
+}
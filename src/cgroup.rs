@@ -0,0 +1,142 @@
+//! Optional enforcement of the slots requested by a job using cgroup v2.
+//!
+//! When `hstart --cgroup` points at a delegated subtree, each job is placed
+//! in its own child cgroup named `<scheduler>-<instance>-<jobid>`, with
+//! `cpuset.cpus`, `cpu.max`, and `memory.max` derived from the queue indices
+//! it was given.  The instance is the time hstart was run, as job IDs start
+//! again from 1 each time, and a job's cgroup cannot be removed while any of
+//! its processes linger.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const CPU_PERIOD: usize = 100000;
+
+/// Parse a size such as "512M" or "32G" into bytes.  Suffixes are powers of 1024.
+pub fn parse_bytes(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last()? {
+        'K' | 'k' => (&s[..s.len()-1], 1 << 10),
+        'M' | 'm' => (&s[..s.len()-1], 1 << 20),
+        'G' | 'g' => (&s[..s.len()-1], 1 << 30),
+        'T' | 't' => (&s[..s.len()-1], 1 << 40),
+        _ => (s, 1),
+    };
+    digits.parse::<usize>().ok().map(|x| x * multiplier)
+}
+
+const CONTROLLERS: [(&str, &str); 3] = [("cpu", "cpu.max"), ("cpuset", "cpuset.cpus"),
+                                        ("memory", "memory.max")];
+
+/// Enable the controllers needed by `create` for the children of `root`, one
+/// at a time, so that those which are available can be used even if others
+/// are not.  Returns those enabled, after printing a warning for each which
+/// couldn't be.
+pub fn delegate(root: &Path) -> Result<Vec<&'static str>, Error> {
+    if !root.is_dir() {
+        return Err(Error::new(ErrorKind::NotFound, "no such directory"));
+    }
+    let mut enabled = Vec::new();
+    for (controller, file) in CONTROLLERS {
+        match fs::write(root.join("cgroup.subtree_control"), format!("+{}", controller)) {
+            Ok(()) => enabled.push(controller),
+            Err(error) => eprintln!("warning: couldn't enable the {} controller in cgroup {}: {}.  {} won't be set",
+                                    controller, root.display(), error, file),
+        }
+    }
+    Ok(enabled)
+}
+
+/// Where the cgroup for job `id` is, if cgroups are configured.
+pub fn path(config: &HashMap<String, String>, name: &str, id: &str) -> Option<PathBuf> {
+    let instance = config.get("cgroup_instance").map_or(String::new(), |x| format!("{}-", x));
+    config.get("cgroup").map(|root| PathBuf::from(root).join(format!("{}-{}{}", name, instance, id)))
+}
+
+fn is_enabled(config: &HashMap<String, String>, controller: &str) -> bool {
+    config.get("cgroup_controllers").is_some_and(|x| x.split(',').any(|c| c == controller))
+}
+
+fn try_create(dir: &Path, config: &HashMap<String, String>,
+              nslots_required: &[usize], queue: &str) -> Result<(), Error> {
+    // an earlier job's, now empty, should hstart have been run twice in a second
+    if dir.is_dir() {
+        fs::remove_dir(dir)?;
+    }
+    fs::create_dir(dir)?;
+    let slots: Vec<&str> = queue.split(';').collect();
+
+    let result = (|| {
+        if let Some(iqueue) = config.get("cgroup_cpus").and_then(|x| x.parse::<usize>().ok()) {
+            let n = nslots_required.get(iqueue).copied().unwrap_or(0);
+            if n > 0 && is_enabled(config, "cpuset") {
+                fs::write(dir.join("cpuset.cpus"), slots[iqueue])?;
+            }
+            if n > 0 && is_enabled(config, "cpu") {
+                fs::write(dir.join("cpu.max"), format!("{} {}", n * CPU_PERIOD, CPU_PERIOD))?;
+            }
+        }
+        if let Some(iqueue) = config.get("cgroup_memory_queue").and_then(|x| x.parse::<usize>().ok()) {
+            let bytes = config.get("cgroup_memory_bytes").and_then(|x| x.parse::<usize>().ok()).unwrap_or(0);
+            let n = nslots_required.get(iqueue).copied().unwrap_or(0);
+            if n > 0 && bytes > 0 && is_enabled(config, "memory") {
+                fs::write(dir.join("memory.max"), (n * bytes).to_string())?;
+            }
+        }
+        Ok(())
+    })();
+
+    if result.is_err() {
+        fs::remove_dir(dir).ok();
+    }
+    result
+}
+
+/// Create a cgroup for job `id` with limits derived from its requested slots
+/// and the queue indices assigned to it.  Returns None, after printing a
+/// warning, if cgroups are not configured or could not be written.
+pub fn create(config: &HashMap<String, String>, name: &str, id: &str,
+              nslots_required: &[usize], queue: &str) -> Option<PathBuf> {
+    let dir = path(config, name, id)?;
+    match try_create(&dir, config, nslots_required, queue) {
+        Ok(()) => Some(dir),
+        Err(error) => {
+            eprintln!("warning: couldn't create cgroup {}: {}.  running job {} without resource enforcement",
+                      dir.display(), error, id);
+            None
+        }
+    }
+}
+
+/// Arrange for the job to move itself into `dir` just before it execs, so
+/// that every process it subsequently spawns is confined too.  Should that
+/// fail, the job is not run and spawning it returns the error instead.
+#[cfg(unix)]
+pub fn enter(cmd: &mut Command, dir: &Path) {
+    use std::os::unix::process::CommandExt;
+    let procs = dir.join("cgroup.procs");
+    unsafe {
+        cmd.pre_exec(move || fs::write(&procs, "0"));
+    }
+}
+
+#[cfg(not(unix))]
+pub fn enter(_cmd: &mut Command, _dir: &Path) {}
+
+/// The PIDs of the processes in the cgroup at `dir`.
+pub fn procs(dir: &Path) -> Vec<u32> {
+    match fs::read_to_string(dir.join("cgroup.procs")) {
+        Ok(procs) => procs.lines().filter_map(|l| l.parse().ok()).collect(),
+        Err(_error) => Vec::new(),
+    }
+}
+
+/// Remove a job's cgroup once all of its processes have exited.
+pub fn remove(dir: &Path) {
+    fs::remove_dir(dir).ok();
+}
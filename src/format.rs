@@ -0,0 +1,243 @@
+//! Machine-readable output for scripts and dashboards.  The columns and their
+//! types are documented in the README and only ever added to, never renamed.
+
+use std::cmp::Ordering;
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use crate::{Job, usage::{Usage, format_bytes, format_duration}};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    /// An aligned table for humans, with the header last
+    Text,
+    /// A JSON array of objects
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// Tab-separated values, with the header first
+    Tsv,
+    /// Comma-separated values, with the header first
+    Csv,
+}
+
+/// The columns describing a job, whether queued or finished.  Except for
+/// elapsed, the usage columns are null until the job has finished.
+pub const JOB_COLUMNS: [&str; 29] = ["id", "state", "nslots", "command", "var", "out", "err",
+                                     "append", "dep", "queue", "pid",
+                                     "status", "elapsed", "user", "system", "maxrss", "read", "written",
+                                     "submit_time", "start_time", "name", "tags", "submit_dir",
+                                     "argv", "in", "log", "inputs", "outputs", "time_limit"];
+
+/// The columns hjobs shows in a table unless told otherwise.
+pub const DEFAULT_JOB_COLUMNS: &str = "id,name,state,nslots,elapsed,submit_time,command";
+
+/// The columns hjobs shows in a table instead, should any of the jobs have
+/// finished, with the resources they used in place of when they were submitted.
+pub const DEFAULT_FINISHED_JOB_COLUMNS: &str = "id,name,state,status,nslots,elapsed,user,system,maxrss,read,written,command";
+
+/// Values in a table longer than this are truncated unless told otherwise.
+pub const TRUNCATE_WIDTH: usize = 40;
+
+fn numbers(s: &str) -> Value {
+    s.split(',').filter(|x| !x.is_empty()).filter_map(|x| x.parse::<u64>().ok()).collect()
+}
+
+fn words(s: &str) -> Value {
+    s.split(' ').filter(|x| !x.is_empty()).collect()
+}
+
+/// "pending" or "running" for a job in the stack, and "completed",
+/// "skipped", or "failed" for one in the history.
+pub fn state(job: &Job, usage: Option<&Usage>) -> &'static str {
+    match usage {
+        Some(usage) if usage.is_skipped() => "skipped",
+        Some(usage) if usage.succeeded() => "completed",
+        Some(_) => "failed",
+        None if job.is_running() => "running",
+        None => "pending",
+    }
+}
+
+/// The values of JOB_COLUMNS for `job`, which has finished if `usage` is given.
+pub fn job_row(job: &Job, usage: Option<&Usage>) -> Vec<Value> {
+    let queue = if job.is_running() || usage.is_some() {
+        job.queue.split(';').map(numbers).collect()
+    } else {
+        Value::Null
+    };
+    let integer = |s: &str| s.parse::<u64>().map_or(Value::Null, |t| json!(t));
+    let mut row = vec![json!(job.id.parse::<u64>().unwrap_or(0)), json!(state(job, usage)),
+                       numbers(&job.nslots), json!(job.command),
+                       job.var.iter().map(|(k, v)| format!("{}={}", k, v)).collect(),
+                       json!(job.out), json!(job.err), json!(job.append), words(&job.dep), queue,
+                       integer(&job.pid)];
+    match usage {
+        Some(u) => row.extend([json!(u.status), json!(u.elapsed), json!(u.user), json!(u.system),
+                               json!(u.maxrss), json!(u.read), json!(u.written)]),
+        None => {
+            let elapsed = job.start_time.parse::<u64>().ok()
+                             .map_or(Value::Null, |t| json!(crate::now().parse::<u64>().unwrap().saturating_sub(t)));
+            row.extend([Value::Null, elapsed, Value::Null, Value::Null, Value::Null, Value::Null, Value::Null]);
+        }
+    }
+    row.extend([integer(&job.submit_time), integer(&job.start_time)]);
+    row.push(if job.name.is_empty() { Value::Null } else { json!(job.name) });
+    row.push(Value::Object(job.tags.split(' ').filter_map(|t| t.split_once('='))
+                              .map(|(k, v)| (k.to_string(), json!(v))).collect()));
+    row.push(json!(job.submit_dir));
+    row.push(json!(job.argv));
+    row.push(if job.input.is_empty() { Value::Null } else { json!(job.input) });
+    row.push(if job.log.is_empty() { Value::Null } else { json!(job.log) });
+    row.push(json!(job.inputs));
+    row.push(json!(job.outputs));
+    row.push(integer(&job.time_limit));
+    row
+}
+
+/// Parse a comma-separated list of `columns`, e.g. "id,state,command".
+pub fn parse_columns(list: &str, columns: &[&str]) -> Result<Vec<usize>, String> {
+    list.split(',')
+        .map(|c| columns.iter().position(|x| *x == c).ok_or(format!("unrecognized column {}", c)))
+        .collect()
+}
+
+/// Sort `rows` by a comma-separated list of `columns`, each optionally
+/// prefixed with a minus sign to sort in descending order, e.g. "state,-id".
+pub fn sort_rows(rows: &mut [Vec<Value>], keys: &str, columns: &[&str]) -> Result<(), String> {
+    let keys: Vec<(usize, bool)> = keys.split(',')
+        .map(|k| {
+            let (name, descending) = match k.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (k, false),
+            };
+            parse_columns(name, columns).map(|i| (i[0], descending))
+        })
+        .collect::<Result<_, _>>()?;
+    rows.sort_by(|a, b| {
+        keys.iter().map(|(i, descending)| {
+                        let ordering = compare(&a[*i], &b[*i]);
+                        if *descending { ordering.reverse() } else { ordering }
+                    })
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+    });
+    Ok(())
+}
+
+/// Nulls first, then numbers numerically, and everything else as text.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        (Value::Number(x), Value::Number(y)) => x.as_f64().partial_cmp(&y.as_f64()).unwrap_or(Ordering::Equal),
+        _ => to_field(a).cmp(&to_field(b)),
+    }
+}
+
+/// Format a time in the past, in seconds since the Unix epoch, relative
+/// to now, e.g. "5m ago".
+pub fn format_age(time: u64) -> String {
+    let age = crate::now().parse::<u64>().unwrap().saturating_sub(time);
+    match age {
+        0..=59 => format!("{}s ago", age),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+/// A value of one of JOB_COLUMNS as it is shown in a table.
+pub fn job_field(column: &str, value: &Value) -> String {
+    match (column, value) {
+        (_, Value::Null) => "-".to_string(),
+        ("elapsed" | "time_limit", _) => format_duration(value.as_f64().unwrap_or(0.0) as u64),
+        ("user" | "system", _) => format!("{:.1}", value.as_f64().unwrap_or(0.0)),
+        ("maxrss" | "read" | "written", _) => format_bytes(value.as_u64().unwrap_or(0)),
+        ("submit_time" | "start_time", _) => format_age(value.as_u64().unwrap_or(0)),
+        _ => to_field(value),
+    }
+}
+
+/// Shorten `s` to `width` characters, ending it with an ellipsis if need be.
+pub fn truncate(s: String, width: usize) -> String {
+    if s.chars().count() <= width {
+        s
+    } else {
+        s.chars().take(width - 3).collect::<String>() + "..."
+    }
+}
+
+/// Print `rows` with each column padded to the same width, followed by
+/// the header, as aitch's other tables are.
+pub fn print_table(columns: &[&str], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).chain([columns[i].len()]).max().unwrap())
+        .collect();
+    let line = |fields: Vec<&str>| {
+        let mut line = fields.iter().zip(&widths)
+                             .map(|(f, w)| format!("{:w$}", f, w = w))
+                             .collect::<Vec<_>>().join("  ");
+        line.truncate(line.trim_end().len());
+        println!("{}", line);
+    };
+    for row in rows {
+        line(row.iter().map(|f| f.as_str()).collect());
+    }
+    line(columns.to_vec());
+}
+
+/// A value as a single TSV or CSV field, with lists joined just as they are
+/// on the command line: slot counts and indices by commas, queues by
+/// semicolons, and variables, dependencies, and tags by spaces.
+fn to_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(a) => {
+            let separator = match a.first() {
+                Some(Value::Array(_)) => ";",
+                Some(Value::String(_)) => " ",
+                _ => ",",
+            };
+            a.iter().map(to_field).collect::<Vec<_>>().join(separator)
+        }
+        Value::Object(o) => o.iter().map(|(k, v)| format!("{}={}", k, to_field(v))).collect::<Vec<_>>().join(" "),
+        _ => value.to_string(),
+    }
+}
+
+fn tsv_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Print `rows`, each of which has a value for every one of `columns`, in
+/// any format but Text, which each tool formats itself.
+pub fn print(format: Format, columns: &[&str], rows: &[Vec<Value>]) {
+    let object = |row: &Vec<Value>| -> Value {
+        Value::Object(columns.iter().map(|c| c.to_string()).zip(row.iter().cloned()).collect::<Map<_, _>>())
+    };
+    match format {
+        Format::Text => unreachable!(),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&rows.iter().map(object).collect::<Vec<_>>()).unwrap()),
+        Format::Jsonl => rows.iter().for_each(|r| println!("{}", object(r))),
+        Format::Tsv | Format::Csv => {
+            let (separator, escape): (&str, fn(&str) -> String) = match format {
+                Format::Tsv => ("\t", tsv_escape),
+                _ => (",", csv_escape),
+            };
+            println!("{}", columns.join(separator));
+            for row in rows {
+                println!("{}", row.iter().map(|v| escape(&to_field(v))).collect::<Vec<_>>().join(separator));
+            }
+        }
+    }
+}
@@ -1,10 +1,20 @@
+pub mod capture;
+pub mod cgroup;
+pub mod format;
+pub mod select;
+pub mod signal;
+pub mod usage;
+
 use std::{
-    path::PathBuf,
+    collections::HashMap,
+    path::{Path, PathBuf},
     fs,
-    io::{BufReader, BufWriter, BufRead, Write},
-    process::exit,
+    io::{BufRead, BufReader, BufWriter, Write},
+    process::{Command, exit, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use fslock::LockFile;
+use sysinfo::{Pid, System};
 
 pub fn eprintln_help(path: &mut PathBuf) {
     eprintln!("error reading {}, which means that aitch is either in a bad state or not running.  consider using `hstop -f` (if necessary) followed by `hstart`", path.display());
@@ -24,6 +34,36 @@ pub fn lock_state(path: &mut PathBuf) -> LockFile {
     return file;
 }
 
+/// Parse a duration such as "90", "90s", "30m", "4h", or "2d".  A bare number is in seconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last()? {
+        's' => (&s[..s.len()-1], 1),
+        'm' => (&s[..s.len()-1], 60),
+        'h' => (&s[..s.len()-1], 3600),
+        'd' => (&s[..s.len()-1], 86400),
+        _ => (s, 1),
+    };
+    digits.parse::<f64>().ok()
+          .filter(|x| *x >= 0.0)
+          .map(|x| Duration::from_secs_f64(x * multiplier as f64))
+}
+
+pub fn get_config(path: &mut PathBuf) -> HashMap<String, String> {
+    path.push("config");
+    let config = match fs::read_to_string(&path) {
+        Ok(config_str) => {
+            config_str.lines()
+                      .filter_map(|l| l.split_once('='))
+                      .map(|(k, v)| (k.to_string(), v.to_string()))
+                      .collect()
+        }
+        Err(_error) => HashMap::new(),
+    };
+    path.pop();
+    config
+}
+
 pub fn get_slot_availability(path: &mut PathBuf) -> Vec<Vec<bool>> {
     path.push("slot_availability");
     let mut slot_availability: Vec<Vec<bool>> = match fs::read_to_string(&path) {
@@ -66,6 +106,92 @@ pub fn get_nslots_free(path: &mut PathBuf) -> Vec<usize> {
     return nslots_free;
 }
 
+/// Choose the first free slots in each queue, as many as `nslots` says, and
+/// describe them as stored in a job's queue field, e.g. "0,1;;3".
+pub fn allocate_slots(slot_availability: &[Vec<bool>], nslots: &[usize]) -> String {
+    nslots.iter().zip(slot_availability)
+          .map(|(n, slots)| slots.iter().enumerate()
+                                 .filter(|(_, used)| !**used)
+                                 .take(*n)
+                                 .map(|(i, _)| i.to_string())
+                                 .collect::<Vec<String>>().join(","))
+          .collect::<Vec<String>>().join(";")
+}
+
+/// Whether `s` is a comma-separated list of numbers, as NSLOTS must be.
+pub fn is_nslots(s: &str) -> bool {
+    s.split(',').all(|x| x.parse::<i32>().is_ok())
+}
+
+/// `nslots` with each negative number replaced by the total number of slots
+/// in that queue, e.g. "1,-1" becomes "1,8".
+pub fn expand_nslots(nslots: &str, nslots_total: &[usize]) -> String {
+    nslots.split(',')
+          .map(|x| x.parse::<i32>().unwrap())
+          .zip(nslots_total.iter())
+          .map(|(x,y)| if x>=0 {x} else {(*y).try_into().unwrap()})
+          .map(|x| x.to_string())
+          .collect::<Vec<String>>()
+          .join(",")
+}
+
+/// The ID of the job most recently submitted, or 0 if there has been none.
+pub fn get_last_jobid(path: &mut PathBuf) -> usize {
+    path.push("last_jobid");
+    let fid = fs::File::open(&path).unwrap();
+    let mut buffer = BufReader::new(fid);
+    let mut first_line = String::new();
+    buffer.read_line(&mut first_line).unwrap();
+    path.pop();
+    first_line.parse::<usize>().unwrap()
+}
+
+pub fn write_last_jobid(path: &mut PathBuf, id: usize) {
+    path.push("last_jobid");
+    fs::write(&path, id.to_string()).unwrap();
+    path.pop();
+}
+
+/// VARIABLE=VALUE, as given to --var.
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((variable, value)) if !variable.is_empty() => Ok((variable.to_string(), value.to_string())),
+        _ => Err(format!("{} is not of the form VARIABLE=VALUE", s)),
+    }
+}
+
+/// Job names are matched by name:NAME dependencies and shown in tables, so
+/// must be a single word.
+pub fn check_job_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err("job names must be non-empty and cannot contain spaces".to_string());
+    }
+    Ok(())
+}
+
+/// Relative paths are relative to the directory the job was submitted from,
+/// not wherever hschedule happens to be.
+pub fn absolute(path: String) -> String {
+    std::path::absolute(&path).map(|p| p.display().to_string()).unwrap_or(path)
+}
+
+/// Where a job saves its standard output or error: `pattern` with %j
+/// replaced by the job's ID, %n by the scheduler's name, and %x by the job's
+/// name, or `<id><extension>` in `log_dir` if there is no pattern.
+pub fn log_path(pattern: Option<&str>, log_dir: &Path, extension: &str,
+                id: &str, scheduler: &str, job_name: &str) -> String {
+    match pattern {
+        Some(pattern) => absolute(expand(pattern, &[('j', id), ('n', scheduler), ('x', job_name)])),
+        None => log_dir.join(id.to_string() + extension).display().to_string(),
+    }
+}
+
+/// The argv which runs `script` with the shell.
+pub fn shell_argv(script: &str) -> Vec<String> {
+    let argv = if cfg!(windows) { ["cmd", "/C", script] } else { ["sh", "-c", script] };
+    argv.into_iter().map(String::from).collect()
+}
+
 pub fn update_slot_availability(path: &mut PathBuf, queue: &String, value: bool) {
     let mut slot_availability: Vec<Vec<bool>> = get_slot_availability(path);
     let queue_vec: Vec<Vec<usize>> = queue.split(";")
@@ -98,57 +224,372 @@ pub fn update_slot_availability(path: &mut PathBuf, queue: &String, value: bool)
     path.pop();
 }
 
-pub fn job_stack_reader(path: &mut PathBuf) -> BufReader<fs::File> {
-    path.push("job_stack");
-    let reader = match fs::File::open(&path) {
-        Ok(job_stack) => BufReader::new(job_stack),
-        Err(_error) => {
-            eprintln_help(path);
-            exit(1)
+/// The names of the fields in each record of job_stack, in the order in
+/// which they are stored, one per line.  The file begins with a header
+/// record consisting of these names.
+pub const JOB_FIELDS: [&str; 26] = ["id", "nslots", "command", "var", "out", "err",
+                                    "append", "dep", "queue", "pid", "submit_time", "start_time",
+                                    "name", "tags", "submit_dir", "env", "argv", "in",
+                                    "log", "log_max_size", "notify_cmd", "runner", "lease",
+                                    "inputs", "outputs", "time_limit"];
+
+#[derive(Clone, Default)]
+pub struct Job {
+    pub id: String,
+    pub nslots: String,
+    /// The command line as shown to people, quoted as for sh.  Stored as
+    /// a JSON string, as it may contain newlines.
+    pub command: String,
+    /// Environment variables to set, stored as a JSON array of pairs.
+    pub var: Vec<(String, String)>,
+    pub out: String,
+    pub err: String,
+    pub append: bool,
+    pub dep: String,
+    pub queue: String,
+    pub pid: String,
+    /// Seconds since the Unix epoch.
+    pub submit_time: String,
+    /// Seconds since the Unix epoch, or empty if pending.
+    pub start_time: String,
+    /// Optional, and not necessarily unique.
+    pub name: String,
+    /// KEY=VALUE pairs separated by spaces.
+    pub tags: String,
+    /// The working directory of hsubmit.
+    pub submit_dir: String,
+    /// The environment to run the job in, as a JSON object, or empty to
+    /// inherit that of hschedule.
+    pub env: String,
+    /// The program and its arguments, stored as a JSON array.
+    pub argv: Vec<String>,
+    /// The file to read standard input from, or empty for none.
+    pub input: String,
+    /// The file to capture standard output and error in together, with
+    /// timestamps, or empty to redirect them to out and err instead.
+    pub log: String,
+    /// In bytes, beyond which the log is rotated, or empty for no limit.
+    pub log_max_size: String,
+    /// A command to run once the job has finished.
+    pub notify_cmd: String,
+    /// The PID of the hrun which runs the job itself, attached to a
+    /// terminal, once hschedule has given it its slots, or empty if
+    /// hschedule launches it.
+    pub runner: String,
+    /// For a lease taken by hacquire, which runs nothing but merely holds
+    /// its slots, how many seconds it lasts once it has them, or "forever".
+    /// Empty for jobs.
+    pub lease: String,
+    /// Files the job reads, stored as a JSON array.
+    pub inputs: Vec<String>,
+    /// Files the job writes, stored as a JSON array.  If they all exist and
+    /// are newer than every input, the job is skipped rather than run.
+    pub outputs: Vec<String>,
+    /// In seconds, after which hschedule terminates the job, or empty for
+    /// no limit.
+    pub time_limit: String,
+}
+
+impl Job {
+    fn from_fields(fields: &[String]) -> Job {
+        Job {
+            id: fields[0].clone(),
+            nslots: fields[1].clone(),
+            command: serde_json::from_str(&fields[2]).unwrap_or_default(),
+            var: serde_json::from_str(&fields[3]).unwrap_or_default(),
+            out: fields[4].clone(),
+            err: fields[5].clone(),
+            append: fields[6].trim().parse().unwrap_or(false),
+            dep: fields[7].clone(),
+            queue: fields[8].clone(),
+            pid: fields[9].clone(),
+            submit_time: fields[10].clone(),
+            start_time: fields[11].clone(),
+            name: fields[12].clone(),
+            tags: fields[13].clone(),
+            submit_dir: fields[14].clone(),
+            env: fields[15].clone(),
+            argv: serde_json::from_str(&fields[16]).unwrap_or_default(),
+            input: fields[17].clone(),
+            log: fields[18].clone(),
+            log_max_size: fields[19].clone(),
+            notify_cmd: serde_json::from_str(&fields[20]).unwrap_or_default(),
+            runner: fields[21].clone(),
+            lease: fields[22].clone(),
+            inputs: serde_json::from_str(&fields[23]).unwrap_or_default(),
+            outputs: serde_json::from_str(&fields[24]).unwrap_or_default(),
+            time_limit: fields[25].clone(),
         }
-    };
+    }
+
+    fn to_fields(&self) -> Vec<String> {
+        vec![self.id.clone(), self.nslots.clone(), serde_json::to_string(&self.command).unwrap(),
+             serde_json::to_string(&self.var).unwrap(),
+             self.out.clone(), self.err.clone(), self.append.to_string(), self.dep.clone(),
+             self.queue.clone(), self.pid.clone(), self.submit_time.clone(), self.start_time.clone(),
+             self.name.clone(), self.tags.clone(), self.submit_dir.clone(), self.env.clone(),
+             serde_json::to_string(&self.argv).unwrap(), self.input.clone(),
+             self.log.clone(), self.log_max_size.clone(), serde_json::to_string(&self.notify_cmd).unwrap(),
+             self.runner.clone(), self.lease.clone(),
+             serde_json::to_string(&self.inputs).unwrap(), serde_json::to_string(&self.outputs).unwrap(),
+             self.time_limit.clone()]
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.pid.is_empty()
+    }
+
+    pub fn is_lease(&self) -> bool {
+        !self.lease.is_empty()
+    }
+
+    /// Whether the job declared outputs, all of which exist and were
+    /// modified after every one of its inputs, as make would decide.
+    pub fn is_up_to_date(&self) -> bool {
+        let modified = |file: &String| fs::metadata(file).and_then(|m| m.modified()).ok();
+        let Some(oldest) = self.outputs.iter().map(modified).collect::<Option<Vec<_>>>()
+                                                            .and_then(|times| times.into_iter().min()) else {
+            return false;
+        };
+        self.inputs.iter().all(|file| modified(file).is_some_and(|t| t < oldest))
+    }
+
+    /// Whether this is a lease which has run out.
+    pub fn is_expired(&self) -> bool {
+        match (self.start_time.parse::<u64>(), self.lease.parse::<u64>()) {
+            (Ok(start), Ok(ttl)) => now().parse::<u64>().unwrap() >= start + ttl,
+            _ => false,
+        }
+    }
+
+    /// The value of tag `key`, if it has one.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.split(' ').filter_map(|t| t.split_once('=')).find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+}
+
+/// Replace each % followed by one of the characters in `substitutions`
+/// with its value, e.g. %j with the job's ID.  %% is a literal %, and any
+/// other % is left as is.
+pub fn expand(pattern: &str, substitutions: &[(char, &str)]) -> String {
+    let mut expanded = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some(other) => match substitutions.iter().find(|(k, _)| *k == other) {
+                Some((_, value)) => expanded.push_str(value),
+                None => { expanded.push('%'); expanded.push(other); }
+            },
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// The current time in whole seconds since the Unix epoch, as stored in
+/// submit_time and start_time.
+pub fn now() -> String {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().to_string()
+}
+
+fn read_records(path: &mut PathBuf, filename: &str, nfields: usize) -> Option<Vec<Vec<String>>> {
+    path.push(filename);
+    let records = fs::read_to_string(&path).ok().map(|contents| {
+        contents.lines()
+                .map(|l| l.to_string())
+                .collect::<Vec<String>>()
+                .chunks_exact(nfields)
+                .skip(1)
+                .map(|c| c.to_vec())
+                .collect()
+    });
     path.pop();
-    return reader;
+    records
 }
 
-pub fn delete_job_from_stack(path: &mut PathBuf, id: String) {
-    path.push("job_stack");
-    let job_stack = fs::File::open(&path).unwrap();
-    let reader = BufReader::new(job_stack);
+fn write_records(path: &mut PathBuf, filename: &str, header: &[&str], records: &[Vec<String>]) {
+    path.push(filename.to_string() + "_new");
+    let file_new = fs::File::create(&path).unwrap();
+    let mut writer = BufWriter::new(file_new);
+    for field in header {
+        writeln!(writer, "{}", field).unwrap();
+    }
+    for record in records {
+        for field in record {
+            writeln!(writer, "{}", field).unwrap();
+        }
+    }
+    writer.flush().unwrap();
+    let path_new = path.clone();
     path.pop();
-    path.push("job_stack_new");
-    let job_stack_new = fs::File::create(&path).unwrap();
-    let mut writer = BufWriter::new(job_stack_new);
+
+    path.push(filename);
+    fs::rename(&path_new, &path).unwrap();
     path.pop();
+}
 
-    let mut reader_iter = reader.lines().map(|l| l.unwrap());
-    let mut iline: i32 = 0;
-    loop {
-        let binding = reader_iter.next();
-        let line = binding.as_ref();
-        if line.is_none() { break; }
-        if iline % 10 == 0 && *line.unwrap() == id {
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-            reader_iter.next();
-        } else {
-            writeln!(writer, "{}", line.unwrap()).unwrap();
-            iline += 1;
+fn append_record(path: &mut PathBuf, filename: &str, header: &[&str], record: &[String]) {
+    path.push(filename);
+    let exists = path.is_file();
+    let mut f = fs::OpenOptions::new().create(true).append(true).open(&path).unwrap();
+    if !exists {
+        for field in header {
+            writeln!(f, "{}", field).unwrap();
         }
     }
-    writer.flush().ok();
-
-    path.push("job_stack");
-    fs::remove_file(&path).ok();
-    let mut path_new = path.clone();
-    path_new.pop();
-    path_new.push("job_stack_new");
-    fs::rename(&path_new, &path).ok();
+    for field in record {
+        writeln!(f, "{}", field).unwrap();
+    }
     path.pop();
 }
+
+pub fn read_job_stack(path: &mut PathBuf) -> Vec<Job> {
+    match read_records(path, "job_stack", JOB_FIELDS.len()) {
+        Some(records) => records.iter().map(|r| Job::from_fields(r)).collect(),
+        None => {
+            path.push("job_stack");
+            eprintln_help(path);
+            exit(1)
+        }
+    }
+}
+
+pub fn write_job_stack(path: &mut PathBuf, jobs: &[Job]) {
+    let records: Vec<Vec<String>> = jobs.iter().map(|j| j.to_fields()).collect();
+    write_records(path, "job_stack", &JOB_FIELDS, &records);
+}
+
+pub fn append_job_to_stack(path: &mut PathBuf, job: &Job) {
+    append_record(path, "job_stack", &JOB_FIELDS, &job.to_fields());
+}
+
+pub fn delete_job_from_stack(path: &mut PathBuf, id: &str) {
+    let jobs: Vec<Job> = read_job_stack(path).into_iter().filter(|j| j.id != id).collect();
+    write_job_stack(path, &jobs);
+}
+
+/// Free the slots held by the lease `job`, record it in the history with
+/// `status`, and run the commands for when a job finishes, just as for the
+/// start of the lease.  It is left to the caller to remove it from the stack.
+pub fn release_lease(path: &mut PathBuf, config: &HashMap<String, String>, scheduler: &str, job: &Job, status: &str) {
+    update_slot_availability(path, &job.queue, false);
+    let elapsed = match (now().parse::<u64>(), job.start_time.parse::<u64>()) {
+        (Ok(now), Ok(start)) => now.saturating_sub(start) as f64,
+        _ => 0.0,
+    };
+    let usage = usage::Usage { status: status.to_string(), elapsed, ..Default::default() };
+    append_job_to_history(path, job, &usage);
+    notify_finished(config, scheduler, job, &usage);
+}
+
+/// The names of the fields which follow those in JOB_FIELDS in each record
+/// of job_history.
+pub const USAGE_FIELDS: [&str; 7] = ["status", "elapsed", "user", "system", "maxrss", "read", "written"];
+
+/// Finished jobs, in the order in which they finished, and the resources
+/// each one actually consumed.
+pub fn read_job_history(path: &mut PathBuf) -> Vec<(Job, usage::Usage)> {
+    let nfields = JOB_FIELDS.len() + USAGE_FIELDS.len();
+    read_records(path, "job_history", nfields)
+        .unwrap_or_default()
+        .iter()
+        .map(|r| (Job::from_fields(&r[..JOB_FIELDS.len()]),
+                  usage::Usage::from_fields(&r[JOB_FIELDS.len()..])))
+        .collect()
+}
+
+pub fn append_job_to_history(path: &mut PathBuf, job: &Job, usage: &usage::Usage) {
+    let header: Vec<&str> = JOB_FIELDS.iter().chain(USAGE_FIELDS.iter()).copied().collect();
+    let mut record = job.to_fields();
+    record.extend(usage.to_fields());
+    append_record(path, "job_history", &header, &record);
+}
+
+/// The process `pid` followed by all of its descendants.  Threads are
+/// excluded, as on some platforms they are listed as processes too.
+pub fn process_tree(sys: &System, pid: Pid) -> Vec<Pid> {
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+        for (child, p) in sys.processes() {
+            if p.parent() == Some(tree[i]) && p.thread_kind().is_none() && !tree.contains(child) {
+                tree.push(*child);
+            }
+        }
+        i += 1;
+    }
+    tree
+}
+
+/// The command which runs `job` once it has been given its slots, in the
+/// directory and optionally the environment it was submitted with, and with
+/// variables telling it which slots those are and describing it to itself,
+/// so it can label its outputs or submit follow-up jobs.
+pub fn job_command(job: &Job, scheduler: &str) -> Command {
+    let mut cmd = Command::new(&job.argv[0]);
+    cmd.args(&job.argv[1..]);
+
+    if !job.submit_dir.is_empty() {
+        cmd.current_dir(&job.submit_dir);
+    }
+    if let Ok(serde_json::Value::Object(env)) = serde_json::from_str::<serde_json::Value>(&job.env) {
+        cmd.env_clear();
+        cmd.envs(env.iter().filter_map(|(k, v)| Some((k, v.as_str()?))));
+    }
+
+    for (iqueue, slots) in job.queue.split(';').enumerate() {
+        cmd.env(format!("QUEUE{}", iqueue), slots);
+    }
+    cmd.envs(job.var.iter().map(|(k, v)| (k, v)));
+    cmd.env("AITCH_JOB_ID", &job.id)
+       .env("AITCH_SCHEDULER_NAME", scheduler)
+       .env("AITCH_NSLOTS", &job.nslots)
+       .env("AITCH_SUBMIT_DIR", &job.submit_dir);
+    if !job.name.is_empty() {
+        cmd.env("AITCH_JOB_NAME", &job.name);
+    }
+    cmd
+}
+
+/// Run a hook or notification `command` with the shell, without waiting for
+/// it, replacing %j with the job's ID, %n with the scheduler's name, %x with
+/// the job's name, %s with its exit status, and %e with the seconds it took.
+pub fn notify(command: &str, scheduler: &str, job: &Job, usage: Option<&usage::Usage>) {
+    let status = usage.map_or(String::new(), |u| u.status.clone());
+    let elapsed = usage.map_or(String::new(), |u| format!("{:.0}", u.elapsed));
+    let command = expand(command, &[('j', &job.id), ('n', scheduler), ('x', &job.name),
+                                    ('s', &status), ('e', &elapsed)]);
+    let argv = shell_argv(&command);
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..])
+       .env("AITCH_JOB_ID", &job.id)
+       .env("AITCH_SCHEDULER_NAME", scheduler)
+       .env("AITCH_JOB_NAME", &job.name)
+       .env("AITCH_JOB_STATUS", &status)
+       .env("AITCH_JOB_ELAPSED", &elapsed)
+       .stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    if !job.submit_dir.is_empty() {
+        cmd.current_dir(&job.submit_dir);
+    }
+    if let Err(error) = cmd.spawn() {
+        eprintln!("warning: couldn't run {}: {}", command, error);
+    }
+}
+
+/// Run the hooks configured with hstart, and the job's own notification,
+/// now that it has finished.
+pub fn notify_finished(config: &HashMap<String, String>, scheduler: &str, job: &Job, usage: &usage::Usage) {
+    let mut commands = vec![config.get("on_job_end")];
+    if !usage.succeeded() {
+        commands.push(config.get("on_job_fail"));
+    }
+    commands.push(Some(&job.notify_cmd).filter(|c| !c.is_empty()));
+    for command in commands.into_iter().flatten() {
+        notify(command, scheduler, job, Some(usage));
+    }
+}
@@ -0,0 +1,140 @@
+//! Choosing jobs by ID, state, name, tag, command, dependency, resource, and
+//! submission time.  Every kind of criterion given must match, any one of
+//! several values of the same kind.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::ValueEnum;
+use wildmatch::WildMatch;
+use crate::{Job, format, usage::Usage};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum State {
+    Pending,
+    Running,
+    Completed,
+    Skipped,
+    Failed,
+    /// Either completed, skipped, or failed
+    Finished,
+}
+
+fn parse_glob(s: &str) -> Result<WildMatch, String> {
+    Ok(WildMatch::new(s))
+}
+
+/// A duration before now, e.g. "30m", or a UTC date and time, e.g. "2024-05-01 12:00".
+fn parse_time(s: &str) -> Result<u64, String> {
+    let time = match crate::parse_duration(s) {
+        Some(ago) => SystemTime::now() - ago,
+        None => humantime::parse_rfc3339_weak(s).or_else(|_| humantime::parse_rfc3339_weak(&(s.to_string() + ":00")))
+                                                .or_else(|_| humantime::parse_rfc3339_weak(&(s.to_string() + " 00:00:00")))
+                                                .map_err(|_| format!("unrecognized time {}", s))?,
+    };
+    Ok(time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+}
+
+/// An inclusive range of job IDs.
+type Range = (u64, u64);
+
+#[derive(clap::Args)]
+pub struct Selectors {
+    /// Job IDs, ranges thereof such as 5-9, and "pending", "running", or "finished"
+    pub jobs: Vec<String>,
+    /// Only jobs in one of these states
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub state: Vec<State>,
+    /// Only jobs whose name matches this pattern, in which * matches any characters and ? any one
+    #[arg(long, value_parser = parse_glob)]
+    pub job_name: Option<WildMatch>,
+    /// KEY=VALUE.  Only jobs with this tag
+    #[arg(long)]
+    pub tag: Vec<String>,
+    /// Only jobs whose command matches this pattern, in which * matches any characters and ? any one
+    #[arg(long, value_parser = parse_glob)]
+    pub command_glob: Option<WildMatch>,
+    /// Only jobs which depend on this job ID
+    #[arg(long)]
+    pub depends_on: Vec<String>,
+    /// Only jobs which requested slots in this queue, counting from 0
+    #[arg(long)]
+    pub uses: Vec<usize>,
+    /// Only jobs submitted after this time, either a duration ago such as 30m, or a UTC date and time such as "2024-05-01 12:00"
+    #[arg(long, value_parser = parse_time)]
+    pub submitted_after: Option<u64>,
+}
+
+impl Selectors {
+    /// Split the positional arguments into ID ranges and states.
+    fn ids_and_states(&self) -> Result<(Vec<Range>, Vec<State>), String> {
+        let mut ids = Vec::new();
+        let mut states = self.state.clone();
+        for token in self.jobs.iter() {
+            match token.as_str() {
+                "pending" => states.push(State::Pending),
+                "running" => states.push(State::Running),
+                "finished" => states.push(State::Finished),
+                _ => {
+                    let (first, last) = token.split_once('-').unwrap_or((token, token));
+                    match (first.parse::<u64>(), last.parse::<u64>()) {
+                        (Ok(first), Ok(last)) if first <= last => ids.push((first, last)),
+                        _ => return Err(format!("unrecognized job ID, range, or state {}", token)),
+                    }
+                }
+            }
+        }
+        Ok((ids, states))
+    }
+
+    /// Whether any criteria besides IDs and states were given.
+    fn filtered(&self) -> bool {
+        self.job_name.is_some() || !self.tag.is_empty() || self.command_glob.is_some()
+            || !self.depends_on.is_empty() || !self.uses.is_empty() || self.submitted_after.is_some()
+    }
+
+    /// Whether no criteria at all were given.
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty() && self.state.is_empty() && !self.filtered()
+    }
+
+    /// Whether the only criteria are job IDs, in which case finding no
+    /// jobs is an error.
+    pub fn only_ids(&self) -> bool {
+        match self.ids_and_states() {
+            Ok((ids, states)) => !ids.is_empty() && states.is_empty() && !self.filtered(),
+            Err(_) => false,
+        }
+    }
+
+    /// Those of the queued `jobs` and finished `history` which match.  Unless
+    /// IDs or states are given, finished jobs are excluded.
+    pub fn select<'a>(&self, jobs: &'a [Job], history: &'a [(Job, Usage)])
+            -> Result<Vec<(&'a Job, Option<&'a Usage>)>, String> {
+        let (ids, mut states) = self.ids_and_states()?;
+        if states.is_empty() && ids.is_empty() {
+            states = vec![State::Pending, State::Running];
+        }
+
+        let candidates = jobs.iter().map(|j| (j, None))
+                             .chain(history.iter().map(|(j, u)| (j, Some(u))));
+        Ok(candidates.filter(|(job, usage)| {
+            let id = job.id.parse::<u64>().unwrap_or(0);
+            let state = format::state(job, *usage);
+            (ids.is_empty() || ids.iter().any(|(first, last)| (*first..=*last).contains(&id)))
+            && (states.is_empty() || states.iter().any(|s| match s {
+                    State::Finished => usage.is_some(),
+                    _ => s.to_possible_value().unwrap().get_name() == state,
+                }))
+            && self.job_name.as_ref().is_none_or(|g| !job.name.is_empty() && g.matches(&job.name))
+            && (self.tag.is_empty() || self.tag.iter().any(|t| {
+                    let (key, value) = t.split_once('=').unwrap_or((t, ""));
+                    job.tag(key) == Some(value)
+                }))
+            && self.command_glob.as_ref().is_none_or(|g| g.matches(&job.command))
+            && (self.depends_on.is_empty() || job.dep.split(' ').any(|d| self.depends_on.iter().any(|x| x == d)))
+            && (self.uses.is_empty() || self.uses.iter().any(|q| {
+                    job.nslots.split(',').nth(*q).and_then(|n| n.parse::<usize>().ok()).unwrap_or(0) > 0
+                }))
+            && self.submitted_after.is_none_or(|t| job.submit_time.parse::<u64>().is_ok_and(|s| s > t))
+        }).collect())
+    }
+}
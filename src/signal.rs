@@ -0,0 +1,221 @@
+//! Signalling every process belonging to a job, not just the top-level one.
+//!
+//! On Unix hschedule launches each job as the leader of a new process group,
+//! so a job's processes are its descendants, the other members of its group
+//! (which includes descendants orphaned when their parent exited), and, if
+//! it was confined to one, the members of its cgroup.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessStatus, RefreshKind, Signal, System};
+
+const NAMES: [(&str, Signal); 30] = [
+    ("HUP", Signal::Hangup), ("INT", Signal::Interrupt), ("QUIT", Signal::Quit),
+    ("ILL", Signal::Illegal), ("TRAP", Signal::Trap), ("ABRT", Signal::Abort),
+    ("IOT", Signal::IOT), ("BUS", Signal::Bus), ("FPE", Signal::FloatingPointException),
+    ("KILL", Signal::Kill), ("USR1", Signal::User1), ("SEGV", Signal::Segv),
+    ("USR2", Signal::User2), ("PIPE", Signal::Pipe), ("ALRM", Signal::Alarm),
+    ("TERM", Signal::Term), ("CHLD", Signal::Child), ("CONT", Signal::Continue),
+    ("STOP", Signal::Stop), ("TSTP", Signal::TSTP), ("TTIN", Signal::TTIN),
+    ("TTOU", Signal::TTOU), ("URG", Signal::Urgent), ("XCPU", Signal::XCPU),
+    ("XFSZ", Signal::XFSZ), ("VTALRM", Signal::VirtualAlarm), ("PROF", Signal::Profiling),
+    ("WINCH", Signal::Winch), ("IO", Signal::IO), ("SYS", Signal::Sys),
+];
+
+/// Parse a signal name such as "USR1", "SIGUSR1", or "usr1".
+pub fn parse(name: &str) -> Option<Signal> {
+    let name = name.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    NAMES.iter().find(|(n, _)| *n == name).map(|(_, s)| *s)
+}
+
+/// The conventional name of a signal, e.g. "SIGUSR1".
+pub fn name(signal: Signal) -> String {
+    match NAMES.iter().find(|(_, s)| *s == signal) {
+        Some((n, _)) => format!("SIG{}", n),
+        None => signal.to_string(),
+    }
+}
+
+/// Whether `signal` is conventionally used to ask a process to exit, as
+/// opposed to, say, USR1, which a job might handle by checkpointing.
+pub fn terminates(signal: Signal) -> bool {
+    matches!(signal, Signal::Hangup | Signal::Interrupt | Signal::Quit | Signal::Kill | Signal::Term)
+}
+
+/// A fresh snapshot of the process table.
+pub fn processes() -> System {
+    System::new_with_specifics(RefreshKind::new().with_processes(ProcessRefreshKind::everything()))
+}
+
+#[cfg(unix)]
+fn to_libc(signal: Signal) -> Option<libc::c_int> {
+    Some(match signal {
+        Signal::Hangup => libc::SIGHUP,
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Quit => libc::SIGQUIT,
+        Signal::Illegal => libc::SIGILL,
+        Signal::Trap => libc::SIGTRAP,
+        Signal::Abort | Signal::IOT => libc::SIGABRT,
+        Signal::Bus => libc::SIGBUS,
+        Signal::FloatingPointException => libc::SIGFPE,
+        Signal::Kill => libc::SIGKILL,
+        Signal::User1 => libc::SIGUSR1,
+        Signal::Segv => libc::SIGSEGV,
+        Signal::User2 => libc::SIGUSR2,
+        Signal::Pipe => libc::SIGPIPE,
+        Signal::Alarm => libc::SIGALRM,
+        Signal::Term => libc::SIGTERM,
+        Signal::Child => libc::SIGCHLD,
+        Signal::Continue => libc::SIGCONT,
+        Signal::Stop => libc::SIGSTOP,
+        Signal::TSTP => libc::SIGTSTP,
+        Signal::TTIN => libc::SIGTTIN,
+        Signal::TTOU => libc::SIGTTOU,
+        Signal::Urgent => libc::SIGURG,
+        Signal::XCPU => libc::SIGXCPU,
+        Signal::XFSZ => libc::SIGXFSZ,
+        Signal::VirtualAlarm => libc::SIGVTALRM,
+        Signal::Profiling => libc::SIGPROF,
+        Signal::Winch => libc::SIGWINCH,
+        Signal::IO | Signal::Poll => libc::SIGIO,
+        Signal::Sys => libc::SIGSYS,
+        Signal::Power => return None,
+    })
+}
+
+/// Whether `pid` is running, and not merely waiting to be reaped.
+pub fn is_alive(sys: &System, pid: Pid) -> bool {
+    sys.process(pid).is_some_and(|p| p.status() != ProcessStatus::Zombie)
+}
+
+/// Every live process belonging to the job whose top-level process is `pid`.
+pub fn job_processes(sys: &System, pid: Pid, cgroup: Option<&Path>) -> Vec<Pid> {
+    let mut pids: Vec<Pid> = crate::process_tree(sys, pid);
+
+    #[cfg(unix)]
+    for (p, process) in sys.processes() {
+        if process.thread_kind().is_none() && !pids.contains(p)
+                && unsafe { libc::getpgid(p.as_u32() as libc::pid_t) } == pid.as_u32() as libc::pid_t {
+            pids.push(*p);
+        }
+    }
+
+    if let Some(dir) = cgroup {
+        for p in crate::cgroup::procs(dir) {
+            let p = Pid::from_u32(p);
+            if !pids.contains(&p) {
+                pids.push(p);
+            }
+        }
+    }
+
+    pids.retain(|p| is_alive(sys, *p));
+    pids
+}
+
+/// Send `signal` to every process of the job whose top-level process is
+/// `pid`.  Returns the processes signalled, or None if the signal is not
+/// supported on this platform.
+pub fn signal_job(sys: &System, pid: Pid, cgroup: Option<&Path>, signal: Signal) -> Option<Vec<Pid>> {
+    let pids = job_processes(sys, pid, cgroup);
+    let mut others = pids.clone();
+
+    // signal the process group all at once, and then only those processes
+    // which have left it, as a job which handles the signal should only
+    // receive it once
+    #[cfg(unix)]
+    if let Some(signum) = to_libc(signal) {
+        let pgid = pid.as_u32() as libc::pid_t;
+        if unsafe { libc::kill(-pgid, signum) } == 0 {
+            others.retain(|p| unsafe { libc::getpgid(p.as_u32() as libc::pid_t) } != pgid);
+        }
+    }
+
+    if signal_pids(sys, &others, signal) { Some(pids) } else { None }
+}
+
+/// Send `signal` to each of `pids` which is still running.  Returns false if
+/// the signal is not supported on this platform.
+pub fn signal_pids(sys: &System, pids: &[Pid], signal: Signal) -> bool {
+    let mut supported = pids.is_empty();
+    for p in pids.iter() {
+        if let Some(process) = sys.process(*p) {
+            supported |= process.kill_with(signal).is_some();
+        }
+    }
+    supported
+}
+
+/// Of `pids`, those still alive, and their names, after waiting up to
+/// `timeout` for them to exit.
+pub fn lingering(pids: &[Pid], timeout: Duration) -> Vec<(Pid, String)> {
+    let start = Instant::now();
+    loop {
+        let sys = processes();
+        let alive: Vec<(Pid, String)> = pids.iter()
+                                            .filter(|p| is_alive(&sys, **p))
+                                            .map(|p| (*p, sys.process(*p).unwrap().name().to_string()))
+                                            .collect();
+        if alive.is_empty() || start.elapsed() >= timeout {
+            return alive;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Print a warning listing the processes of job `id` which survived `signal`.
+pub fn report_lingering(id: &str, signal: Signal, lingering: &[(Pid, String)]) {
+    if !lingering.is_empty() {
+        eprintln!("warning: job {} still has processes running after {}: {}",
+                  id, name(signal),
+                  lingering.iter().map(|(p, n)| format!("{} ({})", p, n)).collect::<Vec<_>>().join(", "));
+    }
+}
+
+
+/// How long a job which has run out of time is given to exit after SIGTERM
+/// before it is sent SIGKILL.
+pub const TIME_LIMIT_GRACE: Duration = Duration::from_secs(30);
+
+/// A time limit on a running job, started by `time_limit`.
+pub struct TimeLimit {
+    stop: Sender<()>,
+    thread: JoinHandle<bool>,
+}
+
+impl TimeLimit {
+    /// Cancel the limit, now that the job has exited.  Returns whether it
+    /// had already been reached.
+    pub fn stop(self) -> bool {
+        self.stop.send(()).ok();
+        self.thread.join().unwrap_or(false)
+    }
+}
+
+/// Should the job whose top-level process is `pid` still be running after
+/// `limit`, send SIGTERM to all of its processes, and SIGKILL to any still
+/// running TIME_LIMIT_GRACE later.
+pub fn time_limit(pid: Pid, cgroup: Option<PathBuf>, limit: Duration) -> TimeLimit {
+    let (stop, stopped) = mpsc::channel();
+    let thread = thread::spawn(move || {
+        if stopped.recv_timeout(limit) != Err(RecvTimeoutError::Timeout) {
+            return false;
+        }
+        let sys = processes();
+        if let Some(pids) = signal_job(&sys, pid, cgroup.as_deref(), Signal::Term) {
+            let lingering: Vec<Pid> = lingering(&pids, TIME_LIMIT_GRACE).iter().map(|(p, _)| *p).collect();
+            if !lingering.is_empty() {
+                let sys = processes();
+                signal_job(&sys, pid, cgroup.as_deref(), Signal::Kill);
+                signal_pids(&sys, &lingering, Signal::Kill);
+            }
+        }
+        true
+    });
+    TimeLimit { stop, thread }
+}
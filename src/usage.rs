@@ -0,0 +1,182 @@
+//! Accounting of the resources a job actually consumed, as opposed to the
+//! slots it requested.
+
+use std::{
+    fs,
+    io,
+    path::Path,
+    process::{Child, ExitStatus},
+    time::Instant,
+};
+
+#[derive(Clone, Default)]
+pub struct Usage {
+    /// The exit code, or the signal which terminated the job, or "error" if it could not be launched,
+    /// or "skipped" if its outputs were already up to date, or "timeout" if it ran out of time,
+    /// or "expired" if it was a lease which outlived its --ttl.
+    pub status: String,
+    /// Wall-clock seconds.
+    pub elapsed: f64,
+    /// CPU seconds spent in user mode.
+    pub user: f64,
+    /// CPU seconds spent in kernel mode.
+    pub system: f64,
+    /// Peak resident set size in bytes.
+    pub maxrss: u64,
+    /// Bytes read from storage.
+    pub read: u64,
+    /// Bytes written to storage.
+    pub written: u64,
+}
+
+impl Usage {
+    pub(crate) fn from_fields(fields: &[String]) -> Usage {
+        Usage {
+            status: fields[0].clone(),
+            elapsed: fields[1].parse().unwrap_or(0.0),
+            user: fields[2].parse().unwrap_or(0.0),
+            system: fields[3].parse().unwrap_or(0.0),
+            maxrss: fields[4].parse().unwrap_or(0),
+            read: fields[5].parse().unwrap_or(0),
+            written: fields[6].parse().unwrap_or(0),
+        }
+    }
+
+    pub(crate) fn to_fields(&self) -> Vec<String> {
+        vec![self.status.clone(), format!("{:.3}", self.elapsed), format!("{:.3}", self.user),
+             format!("{:.3}", self.system), self.maxrss.to_string(), self.read.to_string(),
+             self.written.to_string()]
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.status == "0" || self.is_skipped() || self.status == "expired"
+    }
+
+    pub fn is_skipped(&self) -> bool {
+        self.status == "skipped"
+    }
+
+    /// The status as a shell would report it: the exit code, 128 plus the
+    /// number of the signal which terminated the job, 0 if it was skipped
+    /// or was a lease which expired, or 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_skipped() || self.status == "expired" {
+            return 0;
+        }
+        match self.status.strip_prefix("signal") {
+            Some(signal) => signal.parse::<i32>().map_or(1, |s| 128 + s),
+            None => self.status.parse::<i32>().unwrap_or(1),
+        }
+    }
+
+    /// Wait for the job `child`, started at `start`, to exit, and measure the
+    /// resources it used.  Only the job itself and those of its descendants
+    /// it reaped are counted, not any other process this one has reaped.
+    #[cfg(unix)]
+    pub fn wait(child: &mut Child, start: Instant) -> io::Result<Usage> {
+        use std::os::unix::process::ExitStatusExt;
+        let mut status = 0;
+        let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+        while unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut ru) } < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        let mut usage = Usage {
+            status: status_string(ExitStatus::from_raw(status)),
+            elapsed: start.elapsed().as_secs_f64(),
+            ..Default::default()
+        };
+        usage.user = ru.ru_utime.tv_sec as f64 + ru.ru_utime.tv_usec as f64 / 1e6;
+        usage.system = ru.ru_stime.tv_sec as f64 + ru.ru_stime.tv_usec as f64 / 1e6;
+        // ru_maxrss is in kilobytes everywhere but macOS
+        usage.maxrss = if cfg!(target_os = "macos") { ru.ru_maxrss as u64 } else { ru.ru_maxrss as u64 * 1024 };
+        usage.read = ru.ru_inblock as u64 * 512;
+        usage.written = ru.ru_oublock as u64 * 512;
+        Ok(usage)
+    }
+
+    #[cfg(not(unix))]
+    pub fn wait(child: &mut Child, start: Instant) -> io::Result<Usage> {
+        let status = child.wait()?;
+        Ok(Usage { status: status_string(status), elapsed: start.elapsed().as_secs_f64(), ..Default::default() })
+    }
+
+    /// Add statistics from the job's cgroup, which take precedence as they
+    /// include every descendant whether reaped or not.
+    pub fn add_cgroup(&mut self, dir: &Path) {
+        if let Ok(stat) = fs::read_to_string(dir.join("cpu.stat")) {
+            for (key, value) in stat.lines().filter_map(|l| l.split_once(' ')) {
+                match (key, value.parse::<f64>()) {
+                    ("user_usec", Ok(x)) => self.user = x / 1e6,
+                    ("system_usec", Ok(x)) => self.system = x / 1e6,
+                    _ => {}
+                }
+            }
+        }
+        if let Ok(peak) = fs::read_to_string(dir.join("memory.peak")) {
+            if let Ok(x) = peak.trim().parse::<u64>() {
+                self.maxrss = self.maxrss.max(x);
+            }
+        }
+        if let Ok(stat) = fs::read_to_string(dir.join("io.stat")) {
+            let (mut read, mut written) = (0, 0);
+            for (key, value) in stat.split_whitespace().filter_map(|x| x.split_once('=')) {
+                match (key, value.parse::<u64>()) {
+                    ("rbytes", Ok(x)) => read += x,
+                    ("wbytes", Ok(x)) => written += x,
+                    _ => {}
+                }
+            }
+            self.read = self.read.max(read);
+            self.written = self.written.max(written);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn status_string(status: ExitStatus) -> String {
+    use std::os::unix::process::ExitStatusExt;
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code.to_string(),
+        (None, Some(signal)) => format!("signal{}", signal),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+#[cfg(not(unix))]
+fn status_string(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => code.to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Format a number of bytes with a binary suffix, e.g. 1536 as "1.5K".
+pub fn format_bytes(bytes: u64) -> String {
+    let suffixes = ["", "K", "M", "G", "T"];
+    let mut x = bytes as f64;
+    let mut i = 0;
+    while x >= 1024.0 && i < suffixes.len() - 1 {
+        x /= 1024.0;
+        i += 1;
+    }
+    if i == 0 {
+        bytes.to_string()
+    } else {
+        format!("{:.1}{}", x, suffixes[i])
+    }
+}
+
+/// Format a number of seconds as [[D-]HH:]MM:SS.
+pub fn format_duration(seconds: u64) -> String {
+    let (d, h, m, s) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+    if d > 0 {
+        format!("{}-{:02}:{:02}:{:02}", d, h, m, s)
+    } else if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
@@ -10,13 +10,27 @@ use std::{
     fs::File,
 };
 
-fn wait_for_all_jobs_to_finish(mut cmd: Command) {
-    let mut n = 0;
-    while n<10 && cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).status().unwrap().success() {
-        thread::sleep(time::Duration::from_secs(1));
-        n += 1;
+/// Some of the jobs may fail, and there may be none left, so hwait's exit
+/// code is of no interest, but it must exit of its own accord, and in time,
+/// should hschedule not be on the PATH say.
+fn wait_for_all_jobs_to_finish(name: &str) {
+    let mut cmd = Command::cargo_bin("hwait").unwrap();
+    let mut child = cmd.args(["--name", name, "--all"])
+                       .stderr(Stdio::piped())
+                       .spawn().unwrap();
+    let start = time::Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > time::Duration::from_secs(60) {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            panic!("the jobs of scheduler {} haven't finished after 60s", name);
+        }
+        thread::sleep(time::Duration::from_millis(100));
     }
-    assert_ne!(n, 10);
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.code().is_some() && !stderr.contains("bad state or not running"),
+            "hwait failed: {}", stderr);
 }
 
 #[test]
@@ -190,7 +204,7 @@ fn one_long_job() -> Result<(), Box<dyn std::error::Error>> {
        .arg("1");
     cmd.assert().success().stdout(predicate::str::contains("\n").count(2));
 
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("one_long_job");
 
     let mut cmd = Command::cargo_bin("hjobs")?;
     cmd.args(["--name", "one_long_job"])
@@ -269,7 +283,7 @@ fn two_jobs() -> Result<(), Box<dyn std::error::Error>> {
        .arg("2");
     cmd.assert().success().stdout(predicate::str::contains("\n").count(2));
 
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("two_jobs");
 
     let predicate_fn = predicate::path::is_file();
     assert_eq!(true, predicate_fn.eval(&path));
@@ -350,6 +364,11 @@ fn two_slots() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("hjobs")?;
     cmd.args(["--name", "two_slots"])
        .arg("2");
+    cmd.assert().success().stdout(predicate::str::contains("completed"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "two_slots"])
+       .arg("3");
     cmd.assert().failure().stderr(predicate::str::contains("no such job found"));
 
     let mut cmd = Command::cargo_bin("hjobs")?;
@@ -357,7 +376,7 @@ fn two_slots() -> Result<(), Box<dyn std::error::Error>> {
        .arg("1");
     cmd.assert().success().stdout(predicate::str::contains("\n").count(2));
 
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("two_slots");
 
     let predicate_fn = predicate::path::is_file();
     assert_eq!(true, predicate_fn.eval(&path));
@@ -441,7 +460,7 @@ fn a_dependent_job() -> Result<(), Box<dyn std::error::Error>> {
        .arg("2");
     cmd.assert().success().stdout(predicate::str::contains("\n").count(2));
 
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("a_dependent_job");
 
     let predicate_fn = predicate::path::is_file();
     assert_eq!(true, predicate_fn.eval(&path));
@@ -522,6 +541,11 @@ fn two_queues() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("hjobs")?;
     cmd.args(["--name", "two_queues"])
        .arg("2");
+    cmd.assert().success().stdout(predicate::str::contains("completed"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "two_queues"])
+       .arg("3");
     cmd.assert().failure().stderr(predicate::str::contains("no such job found"));
 
     let mut cmd = Command::cargo_bin("hjobs")?;
@@ -529,7 +553,7 @@ fn two_queues() -> Result<(), Box<dyn std::error::Error>> {
        .arg("1");
     cmd.assert().success().stdout(predicate::str::contains("\n").count(2));
 
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("two_queues");
 
     let predicate_fn = predicate::path::is_file();
     assert_eq!(true, predicate_fn.eval(&path));
@@ -577,10 +601,7 @@ fn redirect() -> Result<(), Box<dyn std::error::Error>> {
                        .spawn().unwrap();
     assert_stdout(&mut child, "1\n");
 
-    let mut cmd = Command::cargo_bin("hjobs")?;
-    cmd.args(["--name", "redirect"])
-       .arg("1");
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("redirect");
 
     let predicate_fn = predicate::path::is_file();
     path.push("redirect.out");
@@ -605,10 +626,7 @@ fn redirect() -> Result<(), Box<dyn std::error::Error>> {
                        .spawn().unwrap();
     assert_stdout(&mut child, "2\n");
 
-    let mut cmd = Command::cargo_bin("hjobs")?;
-    cmd.args(["--name", "redirect"])
-       .arg("2");
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("redirect");
 
     let predicate_fn = predicate::path::is_file();
     path.push("redirect.out");
@@ -627,6 +645,130 @@ fn redirect() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn stdin() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "stdin"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "stdin"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+    std::fs::write(dir.path().join("in.txt"), "shout\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "stdin", "--in", "in.txt", "--out", "upper.out", "1", "tr", "a-z", "A-Z"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "stdin", "--out", "cat.out", "1", "cat"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    wait_for_all_jobs_to_finish("stdin");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("upper.out"))?, "SHOUT\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("cat.out"))?, "");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "stdin", "1", "--columns", "in"])
+       .assert().success().stdout(predicate::str::contains("in.txt"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "stdin"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn captured_log() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "captured_log"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "captured_log"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "captured_log", "--log", "logs/%j.log", "1", "--", "sh", "-c", "echo hello; echo oops >&2; printf bye"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "captured_log", "--log", "chatty.log", "--log-max-size", "1K",
+              "1", "--", "sh", "-c", "seq 1000"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    // a process left in the background mustn't keep the job from finishing
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "captured_log", "--log", "background.log", "1", "--", "sh", "-c", "sleep 15 & echo started"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "captured_log", "--log", "x.log", "--out", "x.out", "1", "true"])
+       .current_dir(dir.path())
+       .assert().failure().stderr(predicate::str::contains("cannot be used with"));
+
+    wait_for_all_jobs_to_finish("captured_log");
+
+    let log = std::fs::read_to_string(dir.path().join("logs/1.log"))?;
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for (tag, text) in [("out", "hello"), ("err", "oops"), ("out", "bye")] {
+        assert!(lines.iter().any(|l| {
+            let fields: Vec<&str> = l.splitn(3, ' ').collect();
+            fields[0].ends_with('Z') && fields[1] == tag && fields[2] == text
+        }), "{} {} not in {}", tag, text, log);
+    }
+
+    let current = std::fs::read_to_string(dir.path().join("chatty.log"))?;
+    let rotated = std::fs::read_to_string(dir.path().join("chatty.log.1"))?;
+    assert!(current.len() <= 1024 && rotated.len() <= 1024);
+    assert!(current.ends_with(" out 1000\n"));
+
+    let background = std::fs::read_to_string(dir.path().join("background.log"))?;
+    assert!(background.ends_with(" out started\n"));
+
+    let mut cmd = Command::cargo_bin("houtput")?;
+    cmd.args(["--name", "captured_log", "1"])
+       .assert().success().stdout(predicate::str::contains(" err oops\n"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "captured_log"])
+       .assert().success();
+
+    Ok(())
+}
+
 #[test]
 fn envvar() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("hstop")?;
@@ -638,9 +780,15 @@ fn envvar() -> Result<(), Box<dyn std::error::Error>> {
        .arg("1")
        .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
 
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push("envvar");
+
     let mut cmd = Command::cargo_bin("hsubmit")?;
     cmd.args(["--name", "envvar"])
-       .args(["1", "--var", "FOO=foo", "--var", "BAR=bar"]);
+       .args(["1", "--var", "FOO=foo", "--var", "BAR=bar"])
+       .current_dir(&path);
     if env::consts::OS == "windows" {
         cmd.args(["powershell", "--", "-command", "dir", "env:"]);
     } else {
@@ -650,15 +798,7 @@ fn envvar() -> Result<(), Box<dyn std::error::Error>> {
                        .spawn().unwrap();
     assert_stdout(&mut child, "1\n");
 
-    let mut cmd = Command::cargo_bin("hjobs")?;
-    cmd.args(["--name", "envvar"])
-       .arg("1");
-    wait_for_all_jobs_to_finish(cmd);
-       
-    let tmpdir = env::temp_dir();
-    let mut path = PathBuf::from(&tmpdir);
-    path.push("aitch");
-    path.push("envvar");
+    wait_for_all_jobs_to_finish("envvar");
 
     path.push("1.out");
     let mut file = File::open(&path).unwrap();
@@ -670,6 +810,11 @@ fn envvar() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         assert!(contents.contains("FOO=foo"));
         assert!(contents.contains("BAR=bar"));
+        assert!(contents.contains("AITCH_JOB_ID=1\n"));
+        assert!(contents.contains("AITCH_SCHEDULER_NAME=envvar\n"));
+        assert!(contents.contains("AITCH_NSLOTS=1\n"));
+        path.pop();
+        assert!(contents.contains(&format!("AITCH_SUBMIT_DIR={}\n", path.display())));
     }
 
     let mut cmd = Command::cargo_bin("hstop")?;
@@ -721,7 +866,7 @@ fn envvar_with_islot() -> Result<(), Box<dyn std::error::Error>> {
        .arg("1");
     cmd.assert().success().stdout(predicate::str::contains("\n").count(2));
        
-    wait_for_all_jobs_to_finish(cmd);
+    wait_for_all_jobs_to_finish("envvar_with_islot");
 
     let tmpdir = env::temp_dir();
     let mut path = PathBuf::from(&tmpdir);
@@ -792,6 +937,40 @@ fn kill() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn stop_force() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "stop_force"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "stop_force"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    // the hschedule waiting for the job inherits hsubmit's output, so it is closed once that exits
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "stop_force", "1", "sleep", "5"]);
+    let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped())
+                   .spawn().unwrap();
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hstop").unwrap();
+    cmd.args(["--name", "stop_force", "--force"])
+       .assert().success();
+
+    let output = child.wait_with_output()?;
+    assert_eq!(String::from_utf8(output.stdout)?, "1\n");
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+
+    Ok(())
+}
+
 #[test]
 fn two_schedulers() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("hstop")?;
@@ -844,3 +1023,1622 @@ fn two_schedulers() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn cgroup() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "cgroup"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "cgroup", "--cgroup", "/no/such/cgroup"])
+       .arg("1")
+       .assert().success().stderr(predicate::str::contains("without resource enforcement"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "cgroup"])
+       .assert().success();
+
+    // a plain directory stands in for a delegated cgroup v2 subtree
+    let tmpdir = env::temp_dir();
+    let mut root = PathBuf::from(&tmpdir);
+    root.push("aitch_cgroup_test");
+    std::fs::remove_dir_all(&root).ok();
+    std::fs::create_dir_all(&root)?;
+
+    // relative to where hstart is run, not to where jobs are submitted from
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "cgroup", "--cgroup", "aitch_cgroup_test"])
+       .current_dir(&tmpdir)
+       .args(["--cgroup-cpus", "0", "--cgroup-memory", "1:1K"])
+       .arg("4,8")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "cgroup"])
+       .arg("2,3");
+    if env::consts::OS == "windows" {
+        cmd.args(["powershell", "--", "-command", "ls"]);
+    } else {
+        cmd.arg("ls");
+    }
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    wait_for_all_jobs_to_finish("cgroup");
+
+    // named after the scheduler, when it was started, and the job
+    let cgroups: Vec<String> = std::fs::read_dir(&root)?.filter_map(|e| e.ok())
+                                     .map(|e| e.file_name().to_string_lossy().to_string())
+                                     .filter(|n| n.starts_with("cgroup-"))
+                                     .collect();
+    assert_eq!(cgroups.len(), 1);
+    assert!(cgroups[0].ends_with("-1"));
+    root.push(&cgroups[0]);
+    assert_eq!(std::fs::read_to_string(root.join("cpuset.cpus"))?, "0,1");
+    assert_eq!(std::fs::read_to_string(root.join("cpu.max"))?, "200000 100000");
+    assert_eq!(std::fs::read_to_string(root.join("memory.max"))?, "3072");
+    root.pop();
+    std::fs::remove_dir_all(&root).ok();
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "cgroup"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn accounting() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "accounting"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "accounting"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hacct")?;
+    cmd.args(["--name", "accounting"])
+       .assert().success().stdout(predicate::str::contains("no finished jobs found"));
+
+    for id in ["1\n", "2\n"] {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "accounting"])
+           .arg("1");
+        if env::consts::OS == "windows" {
+            cmd.args(["powershell", "--", "-command", "ls"]);
+        } else {
+            cmd.arg("ls");
+        }
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, id);
+    }
+
+    wait_for_all_jobs_to_finish("accounting");
+
+    let usage = "\nid +name +state +status +nslots +elapsed +user +system +maxrss +read +written +command\n$";
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "accounting"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::is_match(format!("^1 +- +completed +0 +1 +.* +ls{}", usage))?);
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "accounting"])
+       .arg("finished")
+       .assert().success().stdout(predicate::str::contains("\n").count(3))
+                          .stdout(predicate::str::is_match(usage)?);
+
+    let mut cmd = Command::cargo_bin("hacct")?;
+    cmd.args(["--name", "accounting"])
+       .assert().success().stdout(predicate::str::starts_with("2 0 "))
+                          .stdout(predicate::str::contains("\n").count(2));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "accounting", "--job-name", "listing"])
+       .arg("1");
+    if env::consts::OS == "windows" {
+        cmd.args(["powershell", "--", "-command", "ls"]);
+    } else {
+        cmd.arg("ls");
+    }
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    wait_for_all_jobs_to_finish("accounting");
+
+    let mut cmd = Command::cargo_bin("hacct")?;
+    cmd.args(["--name", "accounting", "--by", "name"])
+       .assert().success().stdout(predicate::str::is_match("^2 0 .* -\n1 0 .* listing\nnjobs .* name\n$")?);
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "accounting"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn accounting_concurrent() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "accounting_concurrent"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "accounting_concurrent"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    // submitted together, the busy job is run and reaped by the hschedule which launched the sleep
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "accounting_concurrent", "--from-file", "-"]);
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped())
+                       .spawn().unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(),
+                              b"1 --shell 'sleep 3'\n\
+                                1 --shell 'i=0; while [ $i -lt 300000 ]; do i=$((i+1)); done'\n").unwrap();
+    let ids: Vec<String> = BufReader::new(child.stdout.take().unwrap()).lines().take(2).collect::<Result<_, _>>().unwrap();
+    assert_eq!(ids, ["1", "2"]);
+    child.wait()?;
+
+    wait_for_all_jobs_to_finish("accounting_concurrent");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    let output = cmd.args(["--name", "accounting_concurrent", "finished", "--sort", "id",
+                           "--columns", "user"])
+                    .output()?;
+    let user: Vec<f64> = String::from_utf8(output.stdout)?.lines().filter_map(|l| l.trim().parse().ok()).collect();
+    assert!(user[1] > 0.1, "busy job used {} s", user[1]);
+    assert!(user[0] < user[1] / 2.0, "sleep was charged {} s", user[0]);
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "accounting_concurrent"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn live() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "live"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "live"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "live"])
+       .arg("1");
+    if env::consts::OS == "windows" {
+        cmd.args(["powershell", "--", "-command", "sleep", "5"]);
+    } else {
+        cmd.args(["sleep", "5"]);
+    }
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    // the subshell exits straight away, orphaning its sleep into the job's process group
+    if env::consts::OS != "windows" {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "live", "1", "--shell", "(sleep 5 &); sleep 5; true"]);
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, "2\n");
+    }
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "live", "--live", "1"])
+       .assert().success().stdout(predicate::str::is_match("^1  +[0-9]+  .* sleep 5\nid  +pid  +cpu  +rss  +elapsed  +nprocs  +command\n$")?);
+
+    if env::consts::OS != "windows" {
+        let mut cmd = Command::cargo_bin("hjobs")?;
+        cmd.args(["--name", "live", "--live", "--format", "jsonl", "2"])
+           .assert().success().stdout(predicate::str::contains("\"nprocs\":3"));
+    }
+
+    wait_for_all_jobs_to_finish("live");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "live", "--live"])
+       .assert().success().stdout(predicate::str::contains("no jobs found"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "live"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn kill_process_group() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "kill_process_group"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "kill_process_group"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push("kill_process_group");
+    let script = path.join("script.sh");
+    let pidfile = path.join("worker.pid");
+    std::fs::write(&script, format!("sleep 30 &\necho $! > {}\nwait\n", pidfile.display()))?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "kill_process_group"])
+       .args(["1", "sh", script.display().to_string().as_str()]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let worker = sysinfo::Pid::from(std::fs::read_to_string(&pidfile)?.trim().parse::<usize>()?);
+    let is_alive = |pid| {
+        let sys = sysinfo::System::new_all();
+        sys.process(pid).is_some_and(|p| p.status() != sysinfo::ProcessStatus::Zombie)
+    };
+    assert!(is_alive(worker));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "kill_process_group"])
+       .arg("1")
+       .assert().success().stderr(predicate::str::contains("still has processes").not());
+
+    assert!(!is_alive(worker));
+
+    wait_for_all_jobs_to_finish("kill_process_group");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "kill_process_group"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn kill_signal() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "kill_signal"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "kill_signal"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push("kill_signal");
+    let checkpoint = path.join("checkpoint");
+    std::fs::write(path.join("checkpoint.sh"),
+                   format!("trap 'touch {}' USR1\nsleep 3 & wait\nsleep 3 & wait\n", checkpoint.display()))?;
+    std::fs::write(path.join("stubborn.sh"), "trap '' TERM\nsleep 30 & wait\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "kill_signal"])
+       .args(["1", "sh", path.join("checkpoint.sh").display().to_string().as_str()]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "kill_signal"])
+       .args(["1", "sh", path.join("stubborn.sh").display().to_string().as_str()]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "kill_signal", "1", "true"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    // only running jobs are sent signals which don't ask them to exit, and pending ones stay queued
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "kill_signal", "--signal", "USR1"])
+       .args(["1", "3"])
+       .assert().success();
+
+    thread::sleep(time::Duration::from_secs(1));
+    assert!(predicate::path::is_file().eval(&checkpoint));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "kill_signal", "pending", "--columns", "id"])
+       .assert().success().stdout("3\nid\n");
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "kill_signal", "--signal", "USR1", "--grace", "1s"])
+       .arg("1")
+       .assert().failure().stderr(predicate::str::contains("--grace can't be used with SIGUSR1"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "kill_signal", "running"])
+       .assert().success().stdout(predicate::str::contains("\n").count(3));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "kill_signal", "--signal", "NOSUCHSIGNAL"])
+       .arg("2")
+       .assert().failure().stderr(predicate::str::contains("unrecognized signal"));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "kill_signal", "--grace", "1s"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("so sent SIGKILL"));
+
+    wait_for_all_jobs_to_finish("kill_signal");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "kill_signal", "2", "--columns", "state,status"])
+       .assert().success().stdout(predicate::str::starts_with("failed  signal9"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "kill_signal"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn formats() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "formats"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "formats"])
+       .arg("2,1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    for (nslots, id) in [("1,0", "1\n"), ("2,1", "2\n")] {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "formats"])
+           .arg(nslots);
+        if env::consts::OS == "windows" {
+            cmd.args(["powershell", "--", "-command", "sleep", "3"]);
+        } else {
+            cmd.args(["sleep", "3"]);
+        }
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, id);
+    }
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    let output = cmd.args(["--name", "formats", "--format", "json"]).output()?;
+    let jobs: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(jobs[0]["id"], 1);
+    assert_eq!(jobs[0]["state"], "running");
+    assert_eq!(jobs[0]["nslots"], serde_json::json!([1, 0]));
+    assert_eq!(jobs[0]["queue"], serde_json::json!([[0], []]));
+    assert_eq!(jobs[1]["state"], "pending");
+    assert!(jobs[1]["pid"].is_null());
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "formats", "--format", "jsonl"])
+       .assert().success().stdout(predicate::str::starts_with("{\"id\":1,\"state\":\"running\""))
+                          .stdout(predicate::str::contains("\n").count(2));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "formats", "--format", "tsv", "pending"])
+       .assert().success().stdout(predicate::str::starts_with("id\tstate\tnslots\t"))
+                          .stdout(predicate::str::contains("\n2\tpending\t2,1\t"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "formats", "--format", "csv", "running"])
+       .assert().success().stdout(predicate::str::contains("\n1,running,\"1,0\","));
+
+    let mut cmd = Command::cargo_bin("hstatus")?;
+    let output = cmd.args(["--name", "formats", "--format", "json"]).output()?;
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(status[0]["name"], "formats");
+    assert_eq!(status[0]["nslots_free"], serde_json::json!([1, 1]));
+    assert_eq!(status[0]["njobs_pending"], 1);
+
+    let mut cmd = Command::cargo_bin("hnslots")?;
+    cmd.args(["--name", "formats", "--format", "csv"])
+       .assert().success().stdout("queue,total,used,free\n0,2,1,1\n1,1,0,1\n");
+
+    wait_for_all_jobs_to_finish("formats");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "formats", "--format", "json"])
+       .assert().success().stdout("[]\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    let output = cmd.args(["--name", "formats", "--format", "json", "finished"]).output()?;
+    let jobs: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(jobs[1]["state"], "completed");
+    assert_eq!(jobs[1]["status"], "0");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "formats"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn job_table() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "job_table"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "job_table"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    for id in ["1\n", "2\n"] {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "job_table"])
+           .arg("1");
+        if env::consts::OS == "windows" {
+            cmd.args(["powershell", "--", "-command", "sleep", "2.00000000000000000000000000000000000000000001"]);
+        } else {
+            cmd.args(["sleep", "2.00000000000000000000000000000000000000000001"]);
+        }
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, id);
+    }
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_table"])
+       .assert().success().stdout(predicate::str::contains("...\n"))
+                          .stdout(predicate::str::ends_with("id  name  state    nslots  elapsed  submit_time  command\n"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_table", "--columns", "id,state", "--sort", "-id"])
+       .assert().success().stdout("2   pending\n1   running\nid  state\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_table", "--columns", "command", "--no-truncate"])
+       .assert().success().stdout(predicate::str::contains("0001\n"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_table", "--columns", "id,nosuchcolumn"])
+       .assert().failure().stderr(predicate::str::contains("unrecognized column nosuchcolumn"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_table", "--sort", "nosuchcolumn"])
+       .assert().failure().stderr(predicate::str::contains("unrecognized column nosuchcolumn"));
+
+    wait_for_all_jobs_to_finish("job_table");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "job_table"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn filters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "filters"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "filters"])
+       .arg("1,1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    for (nslots, dep, id) in [("1,0", "0", "1\n"), ("1,1", "1", "2\n"), ("1,0", "1", "3\n")] {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "filters", "--dep", dep])
+           .arg(nslots);
+        if env::consts::OS == "windows" {
+            cmd.args(["powershell", "--", "-command", "sleep", "2"]);
+        } else {
+            cmd.args(["sleep", "2"]);
+        }
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, id);
+    }
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--columns", "id", "2-3", "1"])
+       .assert().success().stdout("1\n2\n3\nid\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--columns", "id", "--state", "pending", "--depends-on", "1"])
+       .assert().success().stdout("2\n3\nid\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--columns", "id", "--uses", "1"])
+       .assert().success().stdout("2\nid\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--command-glob", "ls*"])
+       .assert().success().stdout("no jobs found\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--columns", "id", "--submitted-after", "1h", "--command-glob", "*2"])
+       .assert().success().stdout("1\n2\n3\nid\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--submitted-after", "2999-01-01"])
+       .assert().success().stdout("no jobs found\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "3-x"])
+       .assert().failure().stderr(predicate::str::contains("unrecognized job ID, range, or state 3-x"));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "7-9"])
+       .assert().failure().stderr(predicate::str::contains("no such job found"));
+
+    wait_for_all_jobs_to_finish("filters");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "filters", "--columns", "id", "--state", "completed", "--uses", "1"])
+       .assert().success().stdout("2\nid\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "filters"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn bulk_kill() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "bulk_kill"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "bulk_kill"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    for id in ["1\n", "2\n", "3\n", "4\n", "5\n", "6\n"] {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "bulk_kill"])
+           .arg("1");
+        if env::consts::OS == "windows" {
+            cmd.args(["powershell", "--", "-command", "sleep", "30"]);
+        } else {
+            cmd.args(["sleep", "30"]);
+        }
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, id);
+    }
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "bulk_kill"])
+       .assert().failure().stderr(predicate::str::contains("specify either the jobs to terminate or --all"));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "bulk_kill", "9-12"])
+       .assert().failure().stderr(predicate::str::contains("couldn't find job 9-12"));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "bulk_kill", "4-5", "--state", "pending"])
+       .assert().success();
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "bulk_kill", "--columns", "id,state"])
+       .assert().success().stdout("1   running\n2   running\n3   pending\n6   pending\nid  state\n");
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "bulk_kill", "--all", "--kill"])
+       .assert().success();
+
+    wait_for_all_jobs_to_finish("bulk_kill");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "bulk_kill", "--columns", "id", "--sort", "id", "finished"])
+       .assert().success().stdout("1\n2\nid\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "bulk_kill"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn job_names() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "job_names"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "job_names"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push("job_names");
+    std::fs::write(path.join("name.sh"), "echo $AITCH_JOB_NAME\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "job_names", "--job-name", "align-sample7", "--tag", "project=fly", "--tag", "stage=2"])
+       .args(["1", "sleep", "2"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "job_names", "--job-name", "summarize", "--tag", "project=fly", "--dep", "name:align-sample7"])
+       .args(["1", "sh", path.join("name.sh").display().to_string().as_str()]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "job_names", "--job-name", "has space"])
+       .args(["1", "ls"])
+       .assert().failure().stderr(predicate::str::contains("cannot contain spaces"));
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_names", "--columns", "id,name,state,tags"])
+       .assert().success().stdout("1   align-sample7  running  project=fly stage=2\n\
+                                   2   summarize      pending  project=fly\n\
+                                   id  name           state    tags\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_names", "--columns", "id", "--tag", "stage=2"])
+       .assert().success().stdout("1\nid\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "job_names", "--columns", "id", "--job-name", "sum*"])
+       .assert().success().stdout("2\nid\n");
+
+    wait_for_all_jobs_to_finish("job_names");
+
+    let mut file = File::open(path.join("2.out"))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    assert_eq!(contents, "summarize\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "job_names", "--job-name", "preprocess-a"])
+       .args(["1", "sleep", "30"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hkill")?;
+    cmd.args(["--name", "job_names", "--job-name", "preprocess*"])
+       .assert().success();
+
+    wait_for_all_jobs_to_finish("job_names");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "job_names"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn submit_dir_and_env() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "submit_dir_and_env"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "submit_dir_and_env"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+    std::fs::write(dir.path().join("where.sh"), "pwd\necho ${FOO:-unset} ${BAR:-unset}\n")?;
+
+    for (export, id) in [("FOO", "1\n"), ("ALL", "2\n"), ("NONE", "3\n")] {
+        let mut cmd = Command::cargo_bin("hsubmit")?;
+        cmd.args(["--name", "submit_dir_and_env", "--export", export, "--out", &format!("{}.txt", export)])
+           .args(["1", "sh", "where.sh"])
+           .current_dir(dir.path())
+           .env("FOO", "foo")
+           .env("BAR", "bar");
+        let mut child = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+        assert_stdout(&mut child, id);
+    }
+
+    wait_for_all_jobs_to_finish("submit_dir_and_env");
+
+    let pwd = dir.path().canonicalize()?;
+    for (export, vars) in [("FOO", "foo unset"), ("ALL", "foo bar"), ("NONE", "unset unset")] {
+        let contents = std::fs::read_to_string(dir.path().join(format!("{}.txt", export)))?;
+        assert_eq!(contents, format!("{}\n{}\n", pwd.display(), vars));
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "submit_dir_and_env"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn quoting() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "quoting"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "quoting"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "quoting", "--var", "FOO=a  b", "--var", "BAR=x=y"])
+       .args(["1", "--", "sh", "-c", "echo \"$FOO|$BAR|$0\"", "it's"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "quoting", "--shell"])
+       .args(["1", "echo one | tr o 0;", "echo 'two  spaces'"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "quoting", "--var", "NOVALUE"])
+       .args(["1", "ls"])
+       .assert().failure().stderr(predicate::str::contains("is not of the form VARIABLE=VALUE"));
+
+    wait_for_all_jobs_to_finish("quoting");
+
+    let tmpdir = env::temp_dir();
+    let mut path = PathBuf::from(&tmpdir);
+    path.push("aitch");
+    path.push("quoting");
+    assert_eq!(std::fs::read_to_string(path.join("1.out"))?, "a  b|x=y|it's\n");
+    assert_eq!(std::fs::read_to_string(path.join("2.out"))?, "0ne\ntwo  spaces\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "quoting", "1", "--columns", "command", "--no-truncate"])
+       .assert().success().stdout(predicate::str::starts_with("sh -c 'echo \"$FOO|$BAR|$0\"' 'it'\\''s'\n"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "quoting"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn directives() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "directives"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "directives"])
+       .arg("2,1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+    let script = dir.path().join("job.sh");
+    std::fs::write(&script, "#!/bin/sh\n\
+                             # says hello\n\
+                             #AITCH --nslots 2,1 --job-name hello\n\
+                             #AITCH --out job.out --var \"GREETING=hello there\"\n\
+                             echo \"$GREETING\" $AITCH_NSLOTS $AITCH_JOB_NAME \"$@\"\n\
+                             #AITCH --tag not=read\n")?;
+    std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+    std::fs::write(dir.path().join("bad.sh"), "#AITCH --no-such-flag 1h\necho\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "directives", "job.sh", "world"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    wait_for_all_jobs_to_finish("directives");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "directives", "--job-name", "goodbye", "--append", "1,0", "job.sh"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "directives", "bad.sh"])
+       .current_dir(dir.path())
+       .assert().failure().stderr(predicate::str::contains("unrecognized directive in bad.sh"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "directives", "ls"])
+       .assert().failure().stderr(predicate::str::contains("the number of slots required must be given"));
+
+    wait_for_all_jobs_to_finish("directives");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("job.out"))?,
+               "hello there 2,1 hello world\nhello there 1,0 goodbye\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "directives", "finished", "--columns", "id,tags"])
+       .assert().success().stdout("1\n2\nid  tags\n");
+
+    // terminated once it runs out of time
+    let script = dir.path().join("slow.sh");
+    std::fs::write(&script, "#!/bin/sh\n#AITCH --nslots 1,0\n#AITCH --time 1\nsleep 30\n")?;
+    std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "directives", "slow.sh"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    let start = time::Instant::now();
+    wait_for_all_jobs_to_finish("directives");
+    assert!(start.elapsed() < time::Duration::from_secs(10));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "directives", "3", "--columns", "state,status,time_limit"])
+       .assert().success().stdout("failed  timeout  0:01\nstate   status   time_limit\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "directives"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn from_file() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "from_file"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "from_file"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+    std::fs::write(dir.path().join("jobs.tsv"), "# nslots\toptions\tcommand\n\
+                                                 1\t--job-name first\techo one\n\
+                                                 \n\
+                                                 2\t--var X=two\t--shell 'echo $X $Y'\n")?;
+    std::fs::write(dir.path().join("bad.tsv"), "1\techo fine\n1\t--no-such-flag\techo\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "from_file", "--var", "Y=default", "--append", "--out", "jobs.out", "--from-file", "jobs.tsv"])
+       .current_dir(dir.path())
+       .assert().success().stdout("1\n2\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "from_file", "--from-file", "-"]);
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped())
+                       .spawn().unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), b"1 true\n1 false\n").unwrap();
+    assert_eq!(String::from_utf8(child.wait_with_output()?.stdout)?, "3\n4\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "from_file", "--from-file", "bad.tsv"])
+       .current_dir(dir.path())
+       .assert().failure().stderr(predicate::str::contains("line 2 of bad.tsv"));
+
+    wait_for_all_jobs_to_finish("from_file");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("jobs.out"))?, "one\ntwo default\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "from_file", "finished", "--sort", "id", "--columns", "id,name,state"])
+       .assert().success().stdout("1   first  completed\n\
+                                   2   -      completed\n\
+                                   3   -      completed\n\
+                                   4   -      failed\n\
+                                   id  name   state\n");
+
+    // jobs submitted together run together if they fit
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "from_file", "--from-file", "-"]);
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::null())
+                       .spawn().unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), b"1 sleep 3\n1 sleep 3\n").unwrap();
+    assert!(child.wait()?.success());
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "from_file", "running", "--columns", "id"])
+       .assert().success().stdout("5\n6\nid\n");
+
+    wait_for_all_jobs_to_finish("from_file");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "from_file"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn log_patterns() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "log_patterns"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let dir = assert_fs::TempDir::new()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "log_patterns", "--log-dir", "default-logs"])
+       .arg("1")
+       .current_dir(dir.path())
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "log_patterns", "1", "echo", "hello"])
+       .current_dir(dir.path())
+       .assert().success().stdout("1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "log_patterns", "--job-name", "greet", "--out", "logs/%x-%j.out", "--err", "logs/%n/100%%.err",
+              "1", "--", "sh", "-c", "echo out; echo err >&2"])
+       .current_dir(dir.path())
+       .assert().success().stdout("2\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "log_patterns", "--out", "logs/%x.out", "1", "echo", "a long line"])
+       .current_dir(dir.path())
+       .assert().success().stdout("3\n");
+
+    wait_for_all_jobs_to_finish("log_patterns");
+
+    // rerunning into an existing --out file replaces it, leaving none of the
+    // longer output before, unless --append is given
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "log_patterns", "--out", "logs/%x.out", "1", "echo", "short"])
+       .current_dir(dir.path())
+       .assert().success().stdout("4\n");
+
+    wait_for_all_jobs_to_finish("log_patterns");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("logs/echo.out"))?, "short\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "log_patterns", "--out", "logs/%x.out", "--append", "1", "echo", "appended"])
+       .current_dir(dir.path())
+       .assert().success().stdout("5\n");
+
+    wait_for_all_jobs_to_finish("log_patterns");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("default-logs/1.out"))?, "hello\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("logs/greet-2.out"))?, "out\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("logs/log_patterns/100%.err"))?, "err\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("logs/echo.out"))?, "short\nappended\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "log_patterns"])
+       .assert().success();
+
+    assert!(dir.path().join("default-logs/1.out").exists());
+
+    Ok(())
+}
+
+#[test]
+fn output() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "output"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "output"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "output", "1", "--", "sh", "-c", "echo a; sleep 2; echo b"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "output", "1", "--", "sh", "-c", "echo c; echo d >&2"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("houtput")?;
+    cmd.args(["--name", "output", "2"])
+       .assert().failure().stderr(predicate::str::contains("job 2 hasn't started yet"));
+
+    let mut cmd = Command::cargo_bin("houtput")?;
+    cmd.args(["--name", "output", "--follow", "1"])
+       .assert().success().stdout("a\nb\n");
+
+    let mut cmd = Command::cargo_bin("houtput")?;
+    cmd.args(["--name", "output", "--follow", "2"])
+       .assert().success().stdout("c\n");
+
+    let mut cmd = Command::cargo_bin("houtput")?;
+    cmd.args(["--name", "output", "--err", "2"])
+       .assert().success().stdout("d\n");
+
+    let mut cmd = Command::cargo_bin("houtput")?;
+    cmd.args(["--name", "output", "3"])
+       .assert().failure().stderr(predicate::str::contains("couldn't find job 3"));
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "output"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn hooks() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "hooks"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let dir = assert_fs::TempDir::new()?;
+    let hooks = dir.path().join("hooks.txt");
+    let hooks = hooks.display();
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "hooks", "1"])
+       .args(["--on-job-start", &format!("echo start %j %x >> {}", hooks)])
+       .args(["--on-job-end", &format!("echo end %j %s >> {}", hooks)])
+       .args(["--on-job-fail", &format!("echo fail $AITCH_JOB_ID $AITCH_JOB_STATUS >> {}", hooks)])
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "hooks", "--job-name", "good", "--notify-cmd", &format!("echo notify %j %x %s >> {}", hooks),
+              "1", "true"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "hooks", "--dep", "1", "1", "false"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    wait_for_all_jobs_to_finish("hooks");
+
+    let mut cmd = Command::cargo_bin("hrun")?;
+    cmd.args(["--name", "hooks", "--job-name", "interactive", "1", "--", "sh", "-c", "exit 4"])
+       .assert().code(4);
+
+    let expected = "end 1 0\nend 2 1\nend 3 4\nfail 2 1\nfail 3 4\nnotify 1 good 0\n\
+                    start 1 good\nstart 2\nstart 3 interactive\n";
+    let mut lines = String::new();
+    for _ in 0..10 {
+        let mut sorted: Vec<String> = std::fs::read_to_string(dir.path().join("hooks.txt")).unwrap_or_default()
+                                          .lines().map(|l| l.to_string() + "\n").collect();
+        sorted.sort();
+        lines = sorted.concat();
+        if lines == expected {
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(200));
+    }
+    assert_eq!(lines, expected);
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "hooks"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn wait() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "wait"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "wait"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "wait", "1", "--", "sh", "-c", "sleep 1; exit 3"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "wait", "1", "sleep", "1"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "wait"])
+       .assert().failure().stderr(predicate::str::contains("specify either the jobs to wait for or --all"));
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "wait", "1"])
+       .assert().code(3).stderr(predicate::str::contains("job 1 failed with status 3"));
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "wait", "2"])
+       .assert().success();
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "wait", "1-2"])
+       .assert().code(1);
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "wait", "9"])
+       .assert().failure().stderr(predicate::str::contains("couldn't find job 9"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "wait", "--wait", "1", "--", "sh", "-c", "exit 5"])
+       .assert().code(5).stdout("3\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "wait", "1", "sleep", "1"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "4\n");
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "wait", "--all"])
+       .assert().success();
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "wait"])
+       .assert().success().stdout("no jobs found\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "wait"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn interactive() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "interactive"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "interactive"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "interactive", "1", "sleep", "1"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hrun")?;
+    cmd.args(["--name", "interactive", "1", "--", "sh", "-c", "read x; echo $x $QUEUE0 $AITCH_JOB_ID; exit 3"]);
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+                       .spawn().unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), b"hello\n").unwrap();
+    let output = child.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout)?, "hello 0 2\n");
+    assert_eq!(String::from_utf8(output.stderr)?, "job 2 is waiting for its slots\n");
+
+    // a job whose hrun has gone is forgotten
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "interactive", "1", "sleep", "1"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    let mut cmd = Command::cargo_bin("hrun")?;
+    cmd.args(["--name", "interactive", "1", "true"]);
+    let mut child = cmd.stderr(Stdio::piped())
+                       .spawn().unwrap();
+    thread::sleep(time::Duration::from_millis(200));
+    child.kill().unwrap();
+    child.wait()?;
+
+    wait_for_all_jobs_to_finish("interactive");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "interactive", "finished", "--sort", "id", "--columns", "id,state,status"])
+       .assert().success().stdout("1   completed  0\n\
+                                   2   failed     3\n\
+                                   3   completed  0\n\
+                                   id  state      status\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "interactive"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn leases() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "leases"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let dir = assert_fs::TempDir::new()?;
+    let hooks = dir.path().join("hooks.txt");
+    let hooks = hooks.display();
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "leases"])
+       .args(["--on-job-start", &format!("echo start %j >> {}", hooks)])
+       .args(["--on-job-end", &format!("echo end %j %s >> {}", hooks)])
+       .args(["--on-job-fail", &format!("echo fail %j >> {}", hooks)])
+       .arg("2,1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let mut cmd = Command::cargo_bin("hacquire")?;
+    cmd.args(["--name", "leases", "1,1"])
+       .assert().success().stdout("AITCH_LEASE_ID=1\nQUEUE0=0\nQUEUE1=0\n");
+
+    // times are in whole seconds, so a 1s lease could run out before hacquire sees it granted
+    let mut cmd = Command::cargo_bin("hacquire")?;
+    cmd.args(["--name", "leases", "--ttl", "2s", "2,0"]);
+    let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped())
+                   .spawn().unwrap();
+    thread::sleep(time::Duration::from_millis(500));
+
+    let mut cmd = Command::cargo_bin("hsubmit").unwrap();
+    cmd.args(["--name", "leases", "0,1", "true"]);
+    let mut submitted = cmd.stdout(Stdio::piped())
+                           .spawn().unwrap();
+    assert_stdout(&mut submitted, "3\n");
+
+    thread::sleep(time::Duration::from_millis(500));
+    let mut cmd = Command::cargo_bin("hjobs").unwrap();
+    cmd.args(["--name", "leases", "--sort", "id", "--columns", "id,state,queue"])
+       .assert().success().stdout("1   running  0;0\n\
+                                   2   pending  -\n\
+                                   3   pending  -\n\
+                                   id  state    queue\n");
+
+    let mut cmd = Command::cargo_bin("hrelease-lease").unwrap();
+    cmd.args(["--name", "leases", "1"])
+       .assert().success();
+
+    let output = child.wait_with_output()?;
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout)?, "AITCH_LEASE_ID=2\nQUEUE0=0,1\nQUEUE1=\n");
+    assert_eq!(String::from_utf8(output.stderr)?, "lease 2 is waiting for its slots\n");
+
+    // leases are only waited for when chosen explicitly
+    wait_for_all_jobs_to_finish("leases");
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "leases", "2"])
+       .assert().success().stderr("");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "leases", "finished", "--sort", "id", "--columns", "id,state,status"])
+       .assert().success().stdout("1   completed  0\n\
+                                   2   completed  expired\n\
+                                   3   completed  0\n\
+                                   id  state      status\n");
+
+    // leases run the same commands as jobs when they start and end, but an expired one hasn't failed
+    let expected = "end 1 0\nend 2 expired\nend 3 0\nstart 1\nstart 2\nstart 3\n";
+    let mut lines = String::new();
+    for _ in 0..10 {
+        let mut sorted: Vec<String> = std::fs::read_to_string(dir.path().join("hooks.txt")).unwrap_or_default()
+                                          .lines().map(|l| l.to_string() + "\n").collect();
+        sorted.sort();
+        lines = sorted.concat();
+        if lines == expected {
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(200));
+    }
+    assert_eq!(lines, expected);
+
+    let mut cmd = Command::cargo_bin("hrelease-lease")?;
+    cmd.args(["--name", "leases", "3"])
+       .assert().failure().stderr(predicate::str::contains("couldn't find lease 3"));
+
+    // hwait --all doesn't wait for a lease with no --ttl
+    let mut cmd = Command::cargo_bin("hacquire")?;
+    cmd.args(["--name", "leases", "1,0"])
+       .assert().success().stdout(predicate::str::starts_with("AITCH_LEASE_ID=4\n"));
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "leases", "1,0", "sleep", "1"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "5\n");
+
+    wait_for_all_jobs_to_finish("leases");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "leases", "--columns", "id,state"])
+       .assert().success().stdout("4   running\n\
+                                   id  state\n");
+
+    let mut cmd = Command::cargo_bin("hrelease-lease")?;
+    cmd.args(["--name", "leases", "4"])
+       .assert().success();
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "leases"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn dag() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "dag"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "dag"])
+       .arg("2")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+    std::fs::write(dir.path().join("pipeline.toml"), "[steps.report]\n\
+                                                      nslots = [1]\n\
+                                                      command = [\"sh\", \"-c\", \"echo report >> order\"]\n\
+                                                      needs = [\"second\", \"first\"]\n\
+                                                      [steps.first]\n\
+                                                      nslots = \"2\"\n\
+                                                      command = \"sleep 1; echo first >> order\"\n\
+                                                      [steps.second]\n\
+                                                      nslots = \"1\"\n\
+                                                      command = \"echo $X >> order\"\n\
+                                                      needs = [\"first\"]\n\
+                                                      var = { X = \"second\" }\n")?;
+    std::fs::write(dir.path().join("cycle.toml"), "[steps.a]\nnslots = \"1\"\ncommand = \"true\"\nneeds = [\"b\"]\n\
+                                                   [steps.b]\nnslots = \"1\"\ncommand = \"true\"\nneeds = [\"a\"]\n")?;
+    std::fs::write(dir.path().join("unknown.toml"), "[steps.a]\nnslots = \"1\"\ncommand = \"true\"\nneeds = [\"z\"]\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit-dag")?;
+    cmd.args(["--name", "dag", "cycle.toml"])
+       .current_dir(dir.path())
+       .assert().failure().stderr("steps a, b need one another in a cycle\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit-dag")?;
+    cmd.args(["--name", "dag", "unknown.toml"])
+       .current_dir(dir.path())
+       .assert().failure().stderr("step a: needs unknown step z\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit-dag")?;
+    cmd.args(["--name", "dag", "pipeline.toml"])
+       .current_dir(dir.path())
+       .assert().success().stdout("first 1\nsecond 2\nreport 3\n");
+
+    wait_for_all_jobs_to_finish("dag");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("order"))?, "first\nsecond\nreport\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "dag", "--dag", "1"])
+       .assert().success().stdout("1 first completed\n  \
+                                     2 second completed\n    \
+                                       3 report completed\n  \
+                                     3 report completed\n");
+
+    // steps which don't need one another run together if they fit
+    std::fs::write(dir.path().join("independent.toml"), "[steps.a]\nnslots = \"1\"\ncommand = \"sleep 3\"\n\
+                                                         [steps.b]\nnslots = \"1\"\ncommand = \"sleep 3\"\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit-dag")?;
+    cmd.args(["--name", "dag", "independent.toml"])
+       .current_dir(dir.path())
+       .stdout(Stdio::null())
+       .status()?;
+
+    thread::sleep(time::Duration::from_secs(1));
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "dag", "running", "--columns", "id,name"])
+       .assert().success().stdout("4   a\n5   b\nid  name\n");
+
+    wait_for_all_jobs_to_finish("dag");
+
+    // a dependency on a later job is ignored, so mustn't make a loop
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "dag", "--dep", "7", "1", "true"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "6\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "dag", "--dep", "6", "1", "true"]);
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "7\n");
+
+    wait_for_all_jobs_to_finish("dag");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "dag", "--dag", "7"])
+       .assert().success().stdout("7 completed\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "dag", "--dag", "6"])
+       .assert().success().stdout("6 completed\n  7 completed\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "dag"])
+       .assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn up_to_date() -> Result<(), Box<dyn std::error::Error>> {
+    if env::consts::OS == "windows" {
+        return Ok(());
+    }
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "up_to_date"])
+       .arg("--force").stderr(Stdio::piped()).status()?;
+
+    let mut cmd = Command::cargo_bin("hstart")?;
+    cmd.args(["--name", "up_to_date"])
+       .arg("1")
+       .assert().success().stdout(predicate::str::contains("scheduler with nslots"));
+
+    let dir = assert_fs::TempDir::new()?;
+    std::fs::write(dir.path().join("input"), "in\n")?;
+    thread::sleep(time::Duration::from_millis(50));
+    std::fs::write(dir.path().join("output"), "out\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "up_to_date", "--inputs", "input", "--outputs", "output", "--shell", "1", "echo ran >> output"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "1\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "up_to_date", "--dep", "1", "--shell", "1", "echo dependent >> after"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "2\n");
+
+    let mut cmd = Command::cargo_bin("hwait")?;
+    cmd.args(["--name", "up_to_date", "1-2"])
+       .assert().success();
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("output"))?, "out\n");
+    assert_eq!(std::fs::read_to_string(dir.path().join("after"))?, "dependent\n");
+
+    thread::sleep(time::Duration::from_millis(50));
+    std::fs::write(dir.path().join("input"), "changed\n")?;
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "up_to_date", "--inputs", "input", "--outputs", "output,missing", "--shell", "1", "echo ran >> output"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "3\n");
+
+    let mut cmd = Command::cargo_bin("hsubmit")?;
+    cmd.args(["--name", "up_to_date", "--inputs", "input", "--outputs", "output", "--shell", "1", "echo ran >> output"])
+       .current_dir(dir.path());
+    let mut child = cmd.stdout(Stdio::piped())
+                       .spawn().unwrap();
+    assert_stdout(&mut child, "4\n");
+
+    wait_for_all_jobs_to_finish("up_to_date");
+
+    assert_eq!(std::fs::read_to_string(dir.path().join("output"))?, "out\nran\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "up_to_date", "finished", "--sort", "id", "--columns", "id,state"])
+       .assert().success().stdout("1   skipped\n\
+                                   2   completed\n\
+                                   3   completed\n\
+                                   4   skipped\n\
+                                   id  state\n");
+
+    let mut cmd = Command::cargo_bin("hjobs")?;
+    cmd.args(["--name", "up_to_date", "--state", "skipped", "--columns", "id"])
+       .assert().success().stdout("1\n4\nid\n");
+
+    let mut cmd = Command::cargo_bin("hstop")?;
+    cmd.args(["--name", "up_to_date"])
+       .assert().success();
+
+    Ok(())
+}